@@ -0,0 +1,72 @@
+//! Stands in for Mumble when testing the link on a machine without it.
+//!
+//! Creates the link segment like Mumble does and polls it like Mumble's Link
+//! plugin, printing whenever an application links or unlinks, and whenever
+//! its context or identity changes. Don't run it alongside a real Mumble.
+//!
+//! Runs until interrupted, then removes the segment again.
+
+use std::process::ExitCode;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::Duration;
+
+use mumble_link::{Frame, LinkHost};
+
+/// Mumble fetches the positions once per audio frame.
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Set by SIGINT or SIGTERM.
+static STOP: AtomicBool = AtomicBool::new(false);
+
+extern "C" fn stop(_signal: libc::c_int) {
+    STOP.store(true, Ordering::Relaxed);
+}
+
+fn main() -> ExitCode {
+    let handler = stop as extern "C" fn(libc::c_int) as libc::sighandler_t;
+    unsafe {
+        libc::signal(libc::SIGINT, handler);
+        libc::signal(libc::SIGTERM, handler);
+    }
+
+    let mut host = match LinkHost::new() {
+        Ok(host) => host,
+        Err(e) => {
+            eprintln!("fake-mumble: unable to create the link: {}", e);
+            return ExitCode::FAILURE;
+        }
+    };
+    println!("Created the Mumble link, waiting for an application");
+
+    let mut last: Option<Frame> = None;
+    while !STOP.load(Ordering::Relaxed) {
+        let frame = host.poll();
+        match (&last, &frame) {
+            (None, Some(frame)) => {
+                println!("Linked to {} ({})", frame.name, frame.description);
+                print_strings(frame);
+            }
+            (Some(last), Some(frame))
+                if last.context != frame.context || last.identity != frame.identity =>
+            {
+                print_strings(frame);
+            }
+            (Some(last), None) => println!("Unlinked from {}", last.name),
+            _ => {}
+        }
+        last = frame;
+        thread::sleep(POLL_INTERVAL);
+    }
+    println!("Removing the Mumble link");
+    drop(host);
+    ExitCode::SUCCESS
+}
+
+fn print_strings(frame: &Frame) {
+    println!(
+        "  context {:?}, identity {:?}",
+        String::from_utf8_lossy(&frame.context),
+        frame.identity
+    );
+}
@@ -0,0 +1,199 @@
+//! Command line tools for debugging the Mumble link.
+//!
+//! `mumblelink watch` attaches to the link segment read-only and keeps printing
+//! what Mumble sees: who is writing to it, whether the tick is advancing, and
+//! whether the positions make sense. It decodes the segment with the library's
+//! own `LinkReader`, so it always agrees with what the library writes.
+
+use std::env;
+use std::fmt::Write;
+use std::process::ExitCode;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use mumble_link::{Frame, LinkReader, Position};
+
+const USAGE: &str = "\
+Usage: mumblelink watch [--interval <ms>] [--once]
+
+Print the contents of the Mumble link every <ms> milliseconds (default 1000).
+With --once, print a single report and exit.";
+
+/// How far a length or dot product may be off before it is reported.
+const TOLERANCE: f32 = 0.01;
+
+struct Options {
+    interval: Duration,
+    once: bool,
+}
+
+impl Options {
+    fn parse(mut args: impl Iterator<Item = String>) -> Result<Options, String> {
+        let mut options = Options {
+            interval: Duration::from_secs(1),
+            once: false,
+        };
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--interval" => {
+                    let ms = args
+                        .next()
+                        .ok_or("--interval needs a value")?
+                        .parse::<u64>()
+                        .map_err(|e| format!("invalid interval: {}", e))?;
+                    options.interval = Duration::from_millis(ms.max(1));
+                }
+                "--once" => options.once = true,
+                _ => return Err(format!("unexpected argument `{}`", arg)),
+            }
+        }
+        Ok(options)
+    }
+}
+
+fn main() -> ExitCode {
+    let mut args = env::args().skip(1);
+    match args.next().as_deref() {
+        Some("watch") => match Options::parse(args) {
+            Ok(options) => watch(&options),
+            Err(e) => {
+                eprintln!("mumblelink: {}\n\n{}", e, USAGE);
+                ExitCode::from(2)
+            }
+        },
+        Some("-h") | Some("--help") => {
+            println!("{}", USAGE);
+            ExitCode::SUCCESS
+        }
+        _ => {
+            eprintln!("{}", USAGE);
+            ExitCode::from(2)
+        }
+    }
+}
+
+fn watch(options: &Options) -> ExitCode {
+    let mut reader: Option<LinkReader> = None;
+    // The previous sample, to work out the tick rate
+    let mut last: Option<(Instant, u32)> = None;
+    loop {
+        if reader.as_ref().is_none_or(LinkReader::is_stale) {
+            last = None;
+            reader = match LinkReader::open() {
+                Ok(reader) => {
+                    println!("Attached to the Mumble link at {}", reader.location());
+                    Some(reader)
+                }
+                Err(e) => {
+                    println!("Waiting for Mumble: {}", e);
+                    if options.once {
+                        return ExitCode::FAILURE;
+                    }
+                    None
+                }
+            };
+        }
+
+        if let Some(ref reader) = reader {
+            let frame = reader.read();
+            let now = Instant::now();
+            let rate = last.map(|(at, tick)| {
+                frame.tick.wrapping_sub(tick) as f64 / now.duration_since(at).as_secs_f64()
+            });
+            last = Some((now, frame.tick));
+
+            // A single sample can't tell whether the tick is advancing, so
+            // `--once` takes two.
+            if rate.is_some() || !options.once {
+                println!("{}", report(&frame, rate));
+                if options.once {
+                    return ExitCode::SUCCESS;
+                }
+            }
+        }
+        thread::sleep(options.interval);
+    }
+}
+
+fn report(frame: &Frame, rate: Option<f64>) -> String {
+    if !frame.is_active() {
+        return "No application is writing to the link".to_owned();
+    }
+
+    let mut out = String::new();
+    let _ = writeln!(
+        out,
+        "owner:    {} ({}), version {}",
+        frame.name, frame.description, frame.version
+    );
+    let _ = match rate {
+        Some(rate) if rate > 0. => writeln!(out, "tick:     {} ({:.1}/s)", frame.tick, rate),
+        Some(_) => writeln!(
+            out,
+            "tick:     {} (not advancing, Mumble ignores the positions)",
+            frame.tick
+        ),
+        None => writeln!(out, "tick:     {}", frame.tick),
+    };
+    let _ = writeln!(out, "identity: {:?}", frame.identity);
+    let _ = writeln!(
+        out,
+        "context:  {:?} ({} bytes)",
+        String::from_utf8_lossy(&frame.context),
+        frame.context.len()
+    );
+    for (name, position) in [("avatar", &frame.avatar), ("camera", &frame.camera)] {
+        let _ = writeln!(
+            out,
+            "{}:   position {:?} front {:?} top {:?}",
+            name, position.position, position.front, position.top
+        );
+        for problem in problems(position) {
+            let _ = writeln!(out, "          warning: {}", problem);
+        }
+    }
+    if frame.avatar.position == [0.; 3] {
+        let _ = writeln!(
+            out,
+            "          warning: the avatar is at the origin, which disables positional audio"
+        );
+    }
+    out.pop();
+    out
+}
+
+/// What is wrong with the `front` and `top` vectors of a position, if anything.
+fn problems(position: &Position) -> Vec<&'static str> {
+    let length = |v: [f32; 3]| (v[0] * v[0] + v[1] * v[1] + v[2] * v[2]).sqrt();
+    let dot = |a: [f32; 3], b: [f32; 3]| a[0] * b[0] + a[1] * b[1] + a[2] * b[2];
+
+    let mut problems = Vec::new();
+    if (length(position.front) - 1.).abs() > TOLERANCE {
+        problems.push("front is not a unit vector");
+    }
+    if (length(position.top) - 1.).abs() > TOLERANCE {
+        problems.push("top is not a unit vector");
+    }
+    if dot(position.front, position.top).abs() > TOLERANCE {
+        problems.push("front and top are not perpendicular");
+    }
+    problems
+}
+
+#[test]
+fn test_problems() {
+    assert!(problems(&Position::default()).is_empty());
+
+    let position = Position {
+        position: [0.; 3],
+        front: [0., 0., 2.],
+        top: [0., 0., 1.],
+    };
+    assert_eq!(
+        vec![
+            "front is not a unit vector",
+            "front and top are not perpendicular"
+        ],
+        problems(&position)
+    );
+}
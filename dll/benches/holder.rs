@@ -0,0 +1,77 @@
+//! Compares the cost of getting at the link from `update` with the current
+//! `Slot` against the `MutStatic<Mutex<Result<..>>>` it replaced, and against
+//! keeping the link in a `SyncLink` inside the slot, which takes its own lock.
+//!
+//! Run with `cargo bench -p MumbleLink`. The link itself is replaced with a
+//! counter, so only the holder is measured.
+
+use std::hint::black_box;
+use std::ops::Deref;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use mumble_link::ErrorCode;
+use mut_static::MutStatic;
+use MumbleLink::slot::Slot;
+
+const ITERATIONS: u32 = 10_000_000;
+
+lazy_static::lazy_static! {
+    static ref OLD: MutStatic<Mutex<Result<u64, ErrorCode>>> = MutStatic::from(Mutex::new(Ok(0)));
+}
+
+static NEW: Slot<u64> = Slot::new();
+
+static SYNC: Slot<Arc<Mutex<u64>>> = Slot::new();
+
+fn old_update() {
+    let link = match OLD.read() {
+        Ok(link) => link,
+        Err(_) => return,
+    };
+    let mut link = match link.lock() {
+        Ok(link) => link,
+        Err(_) => return,
+    };
+    if let Err(e) = link.deref() {
+        eprintln!("{}", e);
+        return;
+    }
+    *link.as_mut().unwrap() += 1;
+}
+
+fn new_update() {
+    let _ = NEW.try_with(|link| *link += 1);
+}
+
+fn sync_update() {
+    let _ = SYNC.try_with(|link| *link.lock().unwrap() += 1);
+}
+
+fn bench(name: &str, f: impl Fn()) -> Duration {
+    // Warm up
+    for _ in 0..ITERATIONS / 10 {
+        black_box(&f)();
+    }
+    let start = Instant::now();
+    for _ in 0..ITERATIONS {
+        black_box(&f)();
+    }
+    let per_iter = start.elapsed() / ITERATIONS;
+    println!("{:>24}: {:>6} ns/update", name, per_iter.as_nanos());
+    per_iter
+}
+
+fn main() {
+    NEW.with(|link| *link = Some(0));
+    SYNC.with(|link| *link = Some(Arc::new(Mutex::new(0))));
+
+    let old = bench("MutStatic<Mutex<Result>>", old_update);
+    let new = bench("Slot", new_update);
+    bench("Slot<SyncLink>", sync_update);
+    println!(
+        "{:>24}: {:.1}x",
+        "speedup",
+        old.as_secs_f64() / new.as_secs_f64().max(f64::EPSILON)
+    );
+}
@@ -0,0 +1,305 @@
+//! User configuration for the JNI library.
+//!
+//! The configuration is read from `$XDG_CONFIG_HOME/mumblelink/config`
+//! (falling back to `~/.config/mumblelink/config`), or from the file named by
+//! `MUMBLELINK_CONFIG`. It consists of `key = value` lines; blank lines and
+//! lines starting with `#` are ignored, and values may be wrapped in double
+//! quotes to keep leading or trailing whitespace:
+//!
+//! ```text
+//! name = Minecraft
+//! description = "Minecraft (1.8.9)"
+//! context = {server}/{dimension}
+//! identity = {user}
+//! log = info
+//! log_file = true
+//! segment = /MumbleLink.1000
+//! uid = 1000
+//! skip_checks = false
+//! watchdog = 2.5
+//! ```
+//!
+//! Each key can be overridden with an environment variable named
+//! `MUMBLELINK_<KEY>`, e.g. `MUMBLELINK_DESCRIPTION`.
+
+use std::env;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::time::Duration;
+
+use log::LevelFilter;
+
+/// The application name shown by Mumble.
+const DEFAULT_NAME: &str = "Minecraft";
+/// The application description shown by Mumble.
+const DEFAULT_DESC: &str = "Minecraft (1.8.9)";
+/// Keeps players on different servers or in different dimensions apart.
+const DEFAULT_CONTEXT: &str = "{server}/{dimension}";
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Config {
+    pub name: String,
+    pub description: String,
+    pub context: Template,
+    pub identity: Template,
+    /// The most detailed level that gets logged.
+    pub log: LevelFilter,
+    /// Whether to also log to a file.
+    pub log_file: bool,
+    /// Where the link is, if not where Mumble normally puts it: the name of a
+    /// shared memory segment, or a path to a file.
+    pub segment: Option<String>,
+    /// Whose Mumble to link to, if not the current user's.
+    pub uid: Option<u32>,
+    /// Whether to use the link even if it belongs to another user or other
+    /// users can access it.
+    pub skip_checks: bool,
+    /// How long the game may go without an update before positional audio is
+    /// turned off, if at all.
+    pub watchdog: Option<Duration>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            name: DEFAULT_NAME.to_owned(),
+            description: DEFAULT_DESC.to_owned(),
+            context: Template::new(DEFAULT_CONTEXT),
+            identity: Template::default(),
+            log: LevelFilter::Warn,
+            log_file: false,
+            segment: None,
+            uid: None,
+            skip_checks: false,
+            watchdog: None,
+        }
+    }
+}
+
+impl Config {
+    /// Load the configuration file and apply environment overrides.
+    ///
+    /// Problems with the file are logged and otherwise ignored, so a broken
+    /// config never keeps the link from starting.
+    pub fn load() -> Config {
+        let mut config = Config::default();
+        if let Some(path) = path() {
+            match fs::read_to_string(&path) {
+                Ok(text) => {
+                    if let Err(e) = config.parse(&text) {
+                        log::error!("{}: {}", path.display(), e);
+                    }
+                }
+                Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+                Err(e) => log::error!("{}: {}", path.display(), e),
+            }
+        }
+        config.apply_env(|key| env::var(key).ok());
+        config
+    }
+
+    /// Parse `key = value` lines on top of the current values, stopping at the
+    /// first malformed line.
+    fn parse(&mut self, text: &str) -> Result<(), String> {
+        for (number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (key, value) = line
+                .split_once('=')
+                .ok_or_else(|| format!("line {}: expected `key = value`", number + 1))?;
+            self.set(key.trim(), unquote(value.trim()))
+                .map_err(|e| format!("line {}: {}", number + 1, e))?;
+        }
+        Ok(())
+    }
+
+    fn apply_env<F: Fn(&str) -> Option<String>>(&mut self, var: F) {
+        for key in KEYS {
+            let name = format!("MUMBLELINK_{}", key.to_uppercase());
+            if let Some(value) = var(&name) {
+                if let Err(e) = self.set(key, &value) {
+                    log::error!("{}: {}", name, e);
+                }
+            }
+        }
+    }
+
+    fn set(&mut self, key: &str, value: &str) -> Result<(), String> {
+        match key {
+            "name" => self.name = value.to_owned(),
+            "description" => self.description = value.to_owned(),
+            "context" => self.context = Template::new(value),
+            "identity" => self.identity = Template::new(value),
+            "log" => {
+                self.log = LevelFilter::from_str(value)
+                    .map_err(|_| format!("invalid log level `{}`", value))?
+            }
+            "log_file" => self.log_file = parse_bool(value)?,
+            "segment" if value.is_empty() => self.segment = None,
+            "segment" => self.segment = Some(value.to_owned()),
+            "uid" if value.is_empty() => self.uid = None,
+            "uid" => {
+                self.uid = Some(
+                    value
+                        .parse()
+                        .map_err(|_| format!("invalid uid `{}`", value))?,
+                )
+            }
+            "skip_checks" => self.skip_checks = parse_bool(value)?,
+            "watchdog" if value.is_empty() => self.watchdog = None,
+            "watchdog" => {
+                let secs = value
+                    .parse::<f64>()
+                    .ok()
+                    .filter(|secs| secs.is_finite() && *secs >= 0.)
+                    .ok_or_else(|| format!("invalid number of seconds `{}`", value))?;
+                self.watchdog = Some(Duration::from_secs_f64(secs)).filter(|d| !d.is_zero());
+            }
+            _ => return Err(format!("unknown key `{}`", key)),
+        }
+        Ok(())
+    }
+}
+
+const KEYS: [&str; 10] = [
+    "name",
+    "description",
+    "context",
+    "identity",
+    "log",
+    "log_file",
+    "segment",
+    "uid",
+    "skip_checks",
+    "watchdog",
+];
+
+fn parse_bool(value: &str) -> Result<bool, String> {
+    match value {
+        "true" | "yes" | "on" | "1" => Ok(true),
+        "false" | "no" | "off" | "0" => Ok(false),
+        _ => Err(format!("expected `true` or `false`, not `{}`", value)),
+    }
+}
+
+fn path() -> Option<PathBuf> {
+    if let Some(path) = env::var_os("MUMBLELINK_CONFIG") {
+        return Some(path.into());
+    }
+    let base = match env::var_os("XDG_CONFIG_HOME") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => PathBuf::from(env::var_os("HOME")?).join(".config"),
+    };
+    Some(base.join("mumblelink").join("config"))
+}
+
+fn unquote(value: &str) -> &str {
+    value
+        .strip_prefix('"')
+        .and_then(|v| v.strip_suffix('"'))
+        .unwrap_or(value)
+}
+
+/// A string with `{variable}` placeholders, filled in from the game state.
+///
+/// Unknown placeholders are left as they are.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Template(String);
+
+impl Template {
+    pub fn new(template: &str) -> Template {
+        Template(template.to_owned())
+    }
+
+    pub fn render(&self, vars: &[(&str, &str)]) -> String {
+        let mut out = String::with_capacity(self.0.len());
+        let mut rest = self.0.as_str();
+        while let Some(start) = rest.find('{') {
+            out.push_str(&rest[..start]);
+            rest = &rest[start..];
+            let value = rest.find('}').and_then(|end| {
+                let value = vars.iter().find(|(k, _)| *k == &rest[1..end])?.1;
+                Some((value, end))
+            });
+            match value {
+                Some((value, end)) => {
+                    out.push_str(value);
+                    rest = &rest[end + 1..];
+                }
+                None => {
+                    out.push('{');
+                    rest = &rest[1..];
+                }
+            }
+        }
+        out.push_str(rest);
+        out
+    }
+}
+
+#[test]
+fn test_parse() {
+    let mut config = Config::default();
+    config
+        .parse(
+            "# comment\n\
+             \n\
+             description = \"Minecraft (1.19) \"\n\
+             context=lunar-{server}\n",
+        )
+        .unwrap();
+    assert_eq!(config.name, DEFAULT_NAME);
+    assert_eq!(config.description, "Minecraft (1.19) ");
+    assert_eq!(config.context, Template::new("lunar-{server}"));
+
+    assert!(config.parse("name Minecraft").is_err());
+    assert!(config.parse("colour = red").is_err());
+    assert!(config.parse("log = loud").is_err());
+
+    config.parse("log = debug\nlog_file = yes").unwrap();
+    assert_eq!(config.log, LevelFilter::Debug);
+    assert!(config.log_file);
+
+    config
+        .parse("segment = /run/mumble/MumbleLink.1000\nuid = 1000")
+        .unwrap();
+    assert_eq!(
+        config.segment.as_deref(),
+        Some("/run/mumble/MumbleLink.1000")
+    );
+    assert_eq!(config.uid, Some(1000));
+    assert!(config.parse("uid = root").is_err());
+    assert!(!config.skip_checks);
+    config.parse("skip_checks = on").unwrap();
+    assert!(config.skip_checks);
+    assert_eq!(config.watchdog, None);
+    config.parse("watchdog = 2.5").unwrap();
+    assert_eq!(config.watchdog, Some(Duration::from_millis(2500)));
+    config.parse("watchdog = 0").unwrap();
+    assert_eq!(config.watchdog, None);
+    assert!(config.parse("watchdog = -1").is_err());
+
+    config.apply_env(|key| match key {
+        "MUMBLELINK_NAME" => Some("Lunar".to_owned()),
+        _ => None,
+    });
+    assert_eq!(config.name, "Lunar");
+    assert_eq!(config.description, "Minecraft (1.19) ");
+}
+
+#[test]
+fn test_template() {
+    let vars = [("user", "steve"), ("server", "mc.example.com")];
+    assert_eq!(Template::new("").render(&vars), "");
+    assert_eq!(Template::new("{user}").render(&vars), "steve");
+    assert_eq!(
+        Template::new("{server}/{dim} {user}").render(&vars),
+        "mc.example.com/{dim} steve"
+    );
+    assert_eq!(Template::new("{{user}}").render(&vars), "{steve}");
+    assert_eq!(Template::new("a { b").render(&vars), "a { b");
+}
@@ -0,0 +1,135 @@
+//! Automatic positional audio context, derived from the server the player is
+//! connected to and the dimension they are in.
+//!
+//! The game state is read with `getServerAddress()` and `getDimension()` on
+//! Solar Patcher's utility accessor. If the accessor doesn't provide them, a
+//! warning is logged once, the placeholders are left empty and the context
+//! stays fixed.
+
+use std::time::{Duration, Instant};
+
+use jni::objects::JString;
+use jni::JNIEnv;
+
+use crate::config::Template;
+use crate::{accessor, guard, JniResult};
+
+/// How often the game is asked for its server and dimension.
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+pub struct AutoContext {
+    template: Template,
+    user: String,
+    /// The context for the last game state, whether or not it was handed out
+    /// by `poll`.
+    current: Option<String>,
+    /// Whether the game set the context itself before the first poll.
+    set_by_game: bool,
+    polled_at: Option<Instant>,
+    /// Whether the game can be asked for its state at all.
+    available: bool,
+}
+
+impl AutoContext {
+    pub fn new(template: Template, user: String) -> AutoContext {
+        AutoContext {
+            template,
+            user,
+            current: None,
+            set_by_game: false,
+            polled_at: None,
+            available: true,
+        }
+    }
+
+    /// The game set the context with `setContext`, which stands until the
+    /// server or dimension changes.
+    pub fn set_by_game(&mut self) {
+        if self.current.is_none() {
+            self.set_by_game = true;
+        }
+    }
+
+    /// Check the game state, returning the new context if it has changed
+    /// since the last call.
+    pub fn poll(&mut self, env: &JNIEnv) -> Option<String> {
+        if let Some(at) = self.polled_at {
+            if at.elapsed() < POLL_INTERVAL {
+                return None;
+            }
+        }
+        self.polled_at = Some(Instant::now());
+
+        let (server, dimension) = if self.available {
+            match game_state(env) {
+                Ok(state) => state,
+                Err(e) => {
+                    // Most likely NoSuchMethodError; don't let it propagate
+                    // into the game, and don't ask again.
+                    guard::clear_exception(env);
+                    log::warn!("Unable to read server and dimension: {}", e);
+                    self.available = false;
+                    (String::new(), String::new())
+                }
+            }
+        } else {
+            (String::new(), String::new())
+        };
+
+        self.changed(&server, &dimension)
+    }
+
+    /// The context for `server` and `dimension`, if it differs from the last
+    /// one.
+    fn changed(&mut self, server: &str, dimension: &str) -> Option<String> {
+        let context = self.template.render(&[
+            ("user", &self.user),
+            ("server", server),
+            ("dimension", dimension),
+        ]);
+        if self.current.as_ref() == Some(&context) {
+            return None;
+        }
+        let first = self.current.replace(context.clone()).is_none();
+        if first && self.set_by_game {
+            return None;
+        }
+        Some(context)
+    }
+}
+
+/// Read the server address (empty in singleplayer) and dimension id.
+fn game_state(env: &JNIEnv) -> JniResult<(String, String)> {
+    let accessor = accessor(env)?;
+
+    let server = env
+        .call_method(accessor, "getServerAddress", "()Ljava/lang/String;", &[])?
+        .l()?;
+    let server = if server.is_null() {
+        String::new()
+    } else {
+        env.get_string(JString::from(server))?.into()
+    };
+
+    let dimension = env.call_method(accessor, "getDimension", "()I", &[])?.i()?;
+
+    Ok((server.to_lowercase(), dimension.to_string()))
+}
+
+#[test]
+fn test_changed() {
+    let template = Template::new("{server}/{dimension}");
+    let mut context = AutoContext::new(template.clone(), "steve".to_owned());
+    assert_eq!(Some("a/0".to_owned()), context.changed("a", "0"));
+    assert_eq!(None, context.changed("a", "0"));
+    assert_eq!(Some("a/-1".to_owned()), context.changed("a", "-1"));
+    context.set_by_game();
+    assert_eq!(None, context.changed("a", "-1"));
+
+    // A context the game set before the first frame is kept until the state
+    // changes
+    let mut context = AutoContext::new(template, "steve".to_owned());
+    context.set_by_game();
+    assert_eq!(None, context.changed("a", "0"));
+    assert_eq!(Some("b/0".to_owned()), context.changed("b", "0"));
+}
@@ -0,0 +1,121 @@
+//! Cached field IDs for the `MumbleInput` object passed to `update`.
+//!
+//! Looking a field up by name costs a string comparison per field, and
+//! `update` reads 24 fields every frame. Instead the IDs are looked up on the
+//! first update and reused for as long as the game passes objects of the same
+//! class. If the class is reloaded, the IDs are looked up again.
+
+use jni::objects::{GlobalRef, JFieldID, JObject};
+use jni::signature::{JavaType, Primitive};
+use jni::sys::jfieldID;
+use jni::JNIEnv;
+use mumble_link::Position;
+
+use crate::JniResult;
+
+const MUMBLE_VEC: &str = "Lcom/moonsworth/client/mumble/MumbleVec;";
+
+pub struct Fields {
+    /// Keeps the class loaded, and with it the field IDs valid.
+    input_class: GlobalRef,
+    /// Keeps the `MumbleVec` class loaded. It can't be reloaded on its own,
+    /// as the input class refers to it.
+    _vec_class: GlobalRef,
+    /// `front`, `top` and `position` of the avatar.
+    avatar: [jfieldID; 3],
+    /// `front`, `top` and `position` of the camera.
+    camera: [jfieldID; 3],
+    /// `xCoord`, `yCoord` and `zCoord` of a `MumbleVec`.
+    coords: [jfieldID; 3],
+}
+
+// Field IDs aren't tied to a thread, and stay valid as long as the classes are
+// loaded, which the global references ensure.
+unsafe impl Send for Fields {}
+
+impl Fields {
+    fn lookup(env: &JNIEnv, input: JObject) -> JniResult<Fields> {
+        let input_class = env.get_object_class(input)?;
+        let field = |name: &str| {
+            env.get_field_id(input_class, name, MUMBLE_VEC)
+                .map(JFieldID::into_inner)
+        };
+        let avatar = [
+            field("avatarFront")?,
+            field("avatarTop")?,
+            field("avatarPosition")?,
+        ];
+        let camera = [
+            field("cameraFront")?,
+            field("cameraTop")?,
+            field("cameraPosition")?,
+        ];
+
+        let vec = get_object(env, input, avatar[0])?;
+        let vec_class = env.get_object_class(vec)?;
+        let coord = |name: &str| {
+            env.get_field_id(vec_class, name, "D")
+                .map(JFieldID::into_inner)
+        };
+        let coords = [coord("xCoord")?, coord("yCoord")?, coord("zCoord")?];
+
+        Ok(Fields {
+            input_class: env.new_global_ref(input_class)?,
+            _vec_class: env.new_global_ref(vec_class)?,
+            avatar,
+            camera,
+            coords,
+        })
+    }
+
+    fn matches(&self, env: &JNIEnv, input: JObject) -> JniResult<bool> {
+        let class = env.get_object_class(input)?;
+        let same = env.is_same_object(class, self.input_class.as_obj())?;
+        env.delete_local_ref(class.into())?;
+        Ok(same)
+    }
+
+    /// Read the avatar and camera positions from `input`, (re)filling `cache`
+    /// if needed.
+    pub fn read(
+        cache: &mut Option<Fields>,
+        env: &JNIEnv,
+        input: JObject,
+    ) -> JniResult<(Position, Position)> {
+        let fields = match cache.take() {
+            Some(fields) if fields.matches(env, input)? => fields,
+            _ => Fields::lookup(env, input)?,
+        };
+        let avatar = fields.position(env, input, &fields.avatar)?;
+        let camera = fields.position(env, input, &fields.camera)?;
+        *cache = Some(fields);
+        Ok((avatar, camera))
+    }
+
+    fn position(&self, env: &JNIEnv, input: JObject, ids: &[jfieldID; 3]) -> JniResult<Position> {
+        Ok(Position {
+            front: self.vec(env, get_object(env, input, ids[0])?)?,
+            top: self.vec(env, get_object(env, input, ids[1])?)?,
+            position: self.vec(env, get_object(env, input, ids[2])?)?,
+        })
+    }
+
+    fn vec(&self, env: &JNIEnv, vec: JObject) -> JniResult<[f32; 3]> {
+        let mut out = [0.; 3];
+        for (out, &id) in out.iter_mut().zip(&self.coords) {
+            let value = env.get_field_unchecked(
+                vec,
+                JFieldID::from(id),
+                JavaType::Primitive(Primitive::Double),
+            )?;
+            *out = value.d()? as f32;
+        }
+        env.delete_local_ref(vec)?;
+        Ok(out)
+    }
+}
+
+fn get_object<'a>(env: &JNIEnv<'a>, obj: JObject<'a>, id: jfieldID) -> JniResult<JObject<'a>> {
+    env.get_field_unchecked(obj, JFieldID::from(id), JavaType::Object(String::new()))?
+        .l()
+}
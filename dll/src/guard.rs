@@ -0,0 +1,99 @@
+//! Keeping Rust panics and JNI errors from crossing into the JVM.
+//!
+//! Unwinding out of an `extern "system"` function aborts the whole process, so
+//! every native runs its body through one of the functions here. Natives which
+//! return a status code log the failure and return an error code; `update`,
+//! which has nothing to return, throws a Java exception instead.
+
+use std::any::Any;
+use std::fmt;
+use std::panic::{self, AssertUnwindSafe};
+
+use jni::sys::jint;
+use jni::JNIEnv;
+
+use crate::JniResult;
+
+/// Thrown for failures which didn't already come with a Java exception.
+const EXCEPTION_CLASS: &str = "java/lang/IllegalStateException";
+
+enum Failure {
+    Jni(jni::errors::Error),
+    Panic(String),
+}
+
+impl fmt::Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Failure::Jni(e) => e.fmt(f),
+            Failure::Panic(msg) => write!(f, "panicked: {}", msg),
+        }
+    }
+}
+
+fn run<T>(f: impl FnOnce() -> JniResult<T>) -> Result<T, Failure> {
+    match panic::catch_unwind(AssertUnwindSafe(f)) {
+        Ok(Ok(value)) => Ok(value),
+        Ok(Err(e)) => Err(Failure::Jni(e)),
+        Err(payload) => Err(Failure::Panic(panic_message(payload))),
+    }
+}
+
+fn panic_message(payload: Box<dyn Any + Send>) -> String {
+    match payload.downcast::<String>() {
+        Ok(msg) => *msg,
+        Err(payload) => match payload.downcast::<&str>() {
+            Ok(msg) => (*msg).to_owned(),
+            Err(_) => "unknown panic".to_owned(),
+        },
+    }
+}
+
+/// Run the body of a native which returns a status code, returning `code` if
+/// it fails. Any Java exception it caused is logged and cleared.
+pub fn or_code(env: &JNIEnv, name: &str, code: jint, f: impl FnOnce() -> JniResult<jint>) -> jint {
+    match run(f) {
+        Ok(value) => value,
+        Err(failure) => {
+            log::error!("{} failed: {} (code {})", name, failure, code);
+            clear_exception(env);
+            code
+        }
+    }
+}
+
+/// Run the body of a native without a return value, turning a failure into a
+/// Java exception. An exception which is already pending is left for the
+/// caller to receive.
+pub fn or_throw(env: &JNIEnv, name: &str, f: impl FnOnce() -> JniResult) {
+    if let Err(failure) = run(f) {
+        if env.exception_check().unwrap_or(true) {
+            return;
+        }
+        let msg = format!("MumbleLink.{}: {}", name, failure);
+        log::error!("{}", msg);
+        let _ = env.throw_new(EXCEPTION_CLASS, &msg);
+    }
+}
+
+/// Log and clear a pending Java exception, for failures which the game doesn't
+/// need to hear about.
+pub fn clear_exception(env: &JNIEnv) {
+    if env.exception_check().unwrap_or(false) {
+        let _ = env.exception_describe();
+        let _ = env.exception_clear();
+    }
+}
+
+#[test]
+fn test_run() {
+    assert!(matches!(run(|| Ok(1)), Ok(1)));
+    assert!(matches!(
+        run(|| -> JniResult<i32> { Err(jni::errors::Error::NullPtr("test")) }),
+        Err(Failure::Jni(_))
+    ));
+    let failure = run(|| -> JniResult<i32> { panic!("boom {}", 1) });
+    assert!(matches!(failure, Err(Failure::Panic(ref msg)) if msg == "boom 1"));
+    let failure = run(|| -> JniResult<i32> { panic!("boom") });
+    assert!(matches!(failure, Err(Failure::Panic(ref msg)) if msg == "boom"));
+}
@@ -1,30 +1,90 @@
 #![allow(non_snake_case)]
-#![feature(io_error_more)]
 
-use std::ops::{Deref, DerefMut};
-use std::sync::Mutex;
+mod config;
+mod context;
+mod fields;
+mod guard;
+mod logging;
+pub mod slot;
 
-use jni::objects::{JObject, JValue};
+use std::sync::mpsc::Receiver;
+
+use jni::objects::{JObject, JString, JValue};
 use jni::sys::jint;
 use jni::JNIEnv;
-use mumble_link::{ErrorCode, MumbleLink, Position};
-use mut_static::MutStatic;
+
+use mumble_link::{
+    ErrorCode, Overrides, ProbingBackend, SharedLink, Status, StatusEvent, Truncated,
+};
+
+use crate::config::Config;
+use crate::context::AutoContext;
+use crate::fields::Fields;
+use crate::slot::{Slot, Unavailable};
 
 type JniResult<T = ()> = std::result::Result<T, jni::errors::Error>;
 
-const MUMBLE_VEC: &str = "Lcom/moonsworth/client/mumble/MumbleVec;";
-const NAME: &str = "Minecraft";
-const DESC: &str = "Minecraft (1.8.9)";
+/// Returned by `init` if it fails unexpectedly.
+const INIT_FAILED: jint = -(ErrorCode::Unknown as jint);
 
-type Link = Mutex<Result<MumbleLink, ErrorCode>>;
+// Return values of `setContext` and `setIdentity`.
+const SET_OK: jint = 0;
+const SET_TRUNCATED: jint = 1;
+const SET_FAILED: jint = -1;
 
-lazy_static::lazy_static! {
-    static ref INSTANCE: MutStatic<Link> = MutStatic::from(Mutex::new(MumbleLink::new(NAME, DESC)));
+// A `SharedLink` keeps retrying in the background, so Mumble may be started
+// after the game and the link will still connect. It lives in the slot rather
+// than a `SyncLink`, so `update` only ever takes the one lock.
+struct State {
+    link: SharedLink,
+    /// Status changes since the last update, to tell the player about.
+    events: Receiver<StatusEvent>,
+    context: AutoContext,
+    fields: Option<Fields>,
+}
+
+static INSTANCE: Slot<State> = Slot::new();
+
+fn reset_link(config: &Config) {
+    INSTANCE.with(|state| {
+        // Release the segment first, or the new link would see the old one as
+        // another application using it.
+        if let Some(old) = state {
+            old.link.deactivate();
+        }
+        *state = Some(open_link(config));
+    });
+}
+
+fn open_link(config: &Config) -> State {
+    let user = std::env::var("USER").unwrap_or_default();
+    let vars = [("user", user.as_str())];
+
+    // The context is filled in by `AutoContext` on the first update
+    let backend = ProbingBackend::with_overrides(&Overrides {
+        segment: config.segment.clone(),
+        uid: config.uid,
+        skip_checks: config.skip_checks,
+    });
+    let mut builder =
+        SharedLink::builder(&config.name, &config.description).backend(Box::new(backend));
+    if let Some(idle) = config.watchdog {
+        builder = builder.watchdog(idle);
+    }
+    let mut link = builder.build();
+    if let Err(e) = link.set_identity(&config.identity.render(&vars)) {
+        log::warn!("Configured identity {}", e);
+    }
+    State {
+        events: link.subscribe(),
+        link,
+        context: AutoContext::new(config.context.clone(), user),
+        fields: None,
+    }
 }
 
-fn reset_link(name: &str, desc: &str) -> Link {
-    let link = Mutex::new(MumbleLink::new(name, desc));
-    std::mem::replace(INSTANCE.write().unwrap().deref_mut(), link)
+fn with_link<R>(f: impl FnOnce(&mut SharedLink) -> R) -> Option<R> {
+    INSTANCE.with(|state| state.as_mut().map(|state| f(&mut state.link)))
 }
 
 #[no_mangle]
@@ -32,36 +92,89 @@ pub extern "system" fn Java_com_moonsworth_client_mumble_MumbleLink_init(
     env: JNIEnv,
     _input: JObject,
 ) -> jint {
-    eprintln!("CALLED Java_com_moonsworth_client_mumble_MumbleLink_init");
-    // TODO: Take name from user
-    match reset_link(NAME, DESC).lock().unwrap().deref() {
-        Ok(_) => 0,
-        Err(e) => {
-            let code = (*e) as i32;
-            eprintln!("MUMBLE ERROR: {}", e);
-
-            let _ = popup(
-                env,
-                "Mumble Link",
-                "Mumble link failed to connect. Is Mumble open?",
-            );
-
-            -code
+    guard::or_code(&env, "init", INIT_FAILED, || {
+        logging::init();
+        let config = Config::load();
+        logging::configure(&config);
+        reset_link(&config);
+
+        // The link keeps retrying on its own, so none of these are fatal.
+        let message = with_link(|link| match link.status() {
+            Status::Active => {
+                log::info!("Mumble link is connected at {}", link.location());
+                None
+            }
+            Status::Closed(e) => {
+                log::warn!(
+                    "Mumble link is not connected (code {}): {}",
+                    e.code() as i32,
+                    e
+                );
+                Some(
+                    "Mumble link is not connected yet. It will connect once Mumble is open."
+                        .to_owned(),
+                )
+            }
+            Status::InUse { name, .. } => Some(format!(
+                "Mumble link is in use by {}. It will connect once that is closed.",
+                name
+            )),
+            Status::Contested { name, .. } => Some(format!(
+                "{} is also using Mumble link, so positional audio may jump around.",
+                name
+            )),
+        });
+        if let Some(Some(message)) = message {
+            popup(&env, "Mumble Link", &message);
         }
+        Ok(0)
+    })
+}
+
+/// What to tell the player about a change in the link's status. Init shows
+/// the initial status, and this every change after that.
+fn announce(event: &StatusEvent) -> String {
+    match event {
+        StatusEvent::Active { .. } => "Mumble link is connected.".to_owned(),
+        StatusEvent::InUse { name, .. } => format!(
+            "Mumble link is in use by {}. It will connect once that is closed.",
+            name
+        ),
+        StatusEvent::Closed { .. } => {
+            "Mumble link was disconnected. It will reconnect once Mumble is open.".to_owned()
+        }
+        StatusEvent::Contested { name, .. } => format!(
+            "{} is also using Mumble link, so positional audio may jump around.",
+            name
+        ),
+        StatusEvent::Restarted => "Mumble was restarted, and the link followed it.".to_owned(),
     }
 }
 
-pub fn popup(env: JNIEnv, name: &str, desc: &str) -> Result<(), jni::errors::Error> {
+/// Solar Patcher's utility accessor, which exposes bits of the game to us.
+fn accessor<'a>(env: &JNIEnv<'a>) -> JniResult<JObject<'a>> {
+    env.get_static_field(
+        "com/grappenmaker/solarpatcher/util/generation/Accessors$Utility",
+        "INSTANCE",
+        "Lcom/grappenmaker/solarpatcher/util/generation/Accessors$Utility;",
+    )?
+    .l()
+}
+
+/// Show a popup in game. This is best-effort: without Solar Patcher it fails,
+/// and the failure is only logged.
+pub fn popup(env: &JNIEnv, name: &str, desc: &str) {
+    if let Err(e) = try_popup(env, name, desc) {
+        log::warn!("Unable to show popup: {}", e);
+        guard::clear_exception(env);
+    }
+}
+
+fn try_popup(env: &JNIEnv, name: &str, desc: &str) -> JniResult {
     let name = env.new_string(name)?.into();
     let desc = env.new_string(desc)?.into();
 
-    let accessor = env
-        .get_static_field(
-            "com/grappenmaker/solarpatcher/util/generation/Accessors$Utility",
-            "INSTANCE",
-            "Lcom/grappenmaker/solarpatcher/util/generation/Accessors$Utility;",
-        )?
-        .l()?;
+    let accessor = accessor(env)?;
 
     env.call_method(
         accessor,
@@ -73,66 +186,103 @@ pub fn popup(env: JNIEnv, name: &str, desc: &str) -> Result<(), jni::errors::Err
     Ok(())
 }
 
+/// Set the context, which decides which players hear each other positionally.
+///
+/// The receiver is unused, so this can be declared as either an instance or a
+/// `static native` method; the latter lets Solar Patcher call it from patched
+/// code without a `MumbleLink` instance. A null string clears the context.
+/// Returns 1 if the context was longer than 256 bytes and was truncated, or
+/// -1 if the link is not initialized.
 #[no_mangle]
-pub extern "system" fn Java_com_moonsworth_client_mumble_MumbleLink_update(
+pub extern "system" fn Java_com_moonsworth_client_mumble_MumbleLink_setContext(
     env: JNIEnv,
     _this: JObject,
-    input: JObject,
-) {
-    eprintln!("CALLED Java_com_moonsworth_client_mumble_MumbleLink_update");
-    // This is error handling hell.
-    let link = INSTANCE.read();
-    if link.is_err() {
-        eprintln!("Mumble Error: Link wasn't initialized (code -1)");
-        return;
-    }
-    let link = link.unwrap();
-    let link = link.lock();
-    if link.is_err() {
-        eprintln!("Mumble Error: Unable to lock link (code -1)");
-        return;
-    }
-    let mut link = link.unwrap();
-    if let Err(i) = link.deref() {
-        let code = (*i) as i32;
-        eprintln!("Mumble Error: {} (code {})", i, code);
-        return;
-    }
-
-    let link = link.as_mut().unwrap();
-
-    let avatar_front = env.get_field(input, "avatarFront", MUMBLE_VEC).unwrap();
-    let avatar_position = env.get_field(input, "avatarPosition", MUMBLE_VEC).unwrap();
-    let avatar_top = env.get_field(input, "avatarTop", MUMBLE_VEC).unwrap();
-
-    let camera_front = env.get_field(input, "cameraFront", MUMBLE_VEC).unwrap();
-    let camera_position = env.get_field(input, "cameraPosition", MUMBLE_VEC).unwrap();
-    let camera_top = env.get_field(input, "cameraTop", MUMBLE_VEC).unwrap();
-
-    let avatar = into_pos(&env, avatar_front, avatar_top, avatar_position).expect("INVALIDE");
-    let camera = into_pos(&env, camera_front, camera_top, camera_position).expect("INVALIDE");
-
-    link.update(avatar, camera);
+    context: JString,
+) -> jint {
+    set_string(env, context, "context", |state, context| {
+        state.context.set_by_game();
+        state.link.set_context(context.as_bytes())
+    })
 }
 
-fn mumble_vec_to_array(env: &JNIEnv, input: JValue) -> JniResult<[f32; 3]> {
-    let obj = input.l()?;
+/// Set the identity of the player, like `setContext`. Returns 1 if the
+/// identity was longer than 255 characters and was truncated.
+#[no_mangle]
+pub extern "system" fn Java_com_moonsworth_client_mumble_MumbleLink_setIdentity(
+    env: JNIEnv,
+    _this: JObject,
+    identity: JString,
+) -> jint {
+    set_string(env, identity, "identity", |state, identity| {
+        state.link.set_identity(identity)
+    })
+}
 
-    let x = env.get_field(obj, "xCoord", "D")?.d()? as f32;
-    let y = env.get_field(obj, "yCoord", "D")?.d()? as f32;
-    let z = env.get_field(obj, "zCoord", "D")?.d()? as f32;
+fn set_string(
+    env: JNIEnv,
+    value: JString,
+    what: &str,
+    set: impl FnOnce(&mut State, &str) -> Result<(), Truncated>,
+) -> jint {
+    guard::or_code(&env, what, SET_FAILED, || {
+        let value: String = if value.is_null() {
+            String::new()
+        } else {
+            env.get_string(value)?.into()
+        };
 
-    Ok([x, y, z])
+        let result = INSTANCE.with(|state| state.as_mut().map(|state| set(state, &value)));
+        Ok(match result {
+            Some(Ok(())) => SET_OK,
+            Some(Err(e)) => {
+                log::warn!("The {} {}", what, e);
+                SET_TRUNCATED
+            }
+            None => {
+                log::warn!("Link wasn't initialized (code -1)");
+                SET_FAILED
+            }
+        })
+    })
 }
 
-fn into_pos(env: &JNIEnv, front: JValue, top: JValue, position: JValue) -> JniResult<Position> {
-    let front = mumble_vec_to_array(env, front)?;
-    let top = mumble_vec_to_array(env, top)?;
-    let position = mumble_vec_to_array(env, position)?;
+#[no_mangle]
+pub extern "system" fn Java_com_moonsworth_client_mumble_MumbleLink_update(
+    env: JNIEnv,
+    _this: JObject,
+    input: JObject,
+) {
+    guard::or_throw(&env, "update", || {
+        let result = INSTANCE.try_with(|state| {
+            if let Some(context) = state.context.poll(&env) {
+                if let Err(e) = state.link.set_context(context.as_bytes()) {
+                    log::warn!("The context {}", e);
+                }
+            }
 
-    Ok(Position {
-        front,
-        top,
-        position,
+            let (avatar, camera) = Fields::read(&mut state.fields, &env, input)?;
+            state.link.update(avatar, camera);
+            for event in state.events.try_iter() {
+                if let StatusEvent::Closed { code, reason } = &event {
+                    log::warn!(
+                        "Mumble link was disconnected (code {}): {}",
+                        *code as i32,
+                        reason
+                    );
+                }
+                popup(&env, "Mumble Link", &announce(&event));
+            }
+            Ok(())
+        });
+        match result {
+            Ok(result) => result,
+            Err(Unavailable::Empty) => {
+                logging::init();
+                log::warn!("Link wasn't initialized (code -1)");
+                Ok(())
+            }
+            // Being reset by another thread; skip this frame.
+            Err(Unavailable::Busy) => Ok(()),
+        }
     })
 }
@@ -0,0 +1,242 @@
+//! Logging for the JNI library and the `mumble-link` crate.
+//!
+//! Messages go to stderr and, if `log_file` is enabled, to
+//! `$XDG_STATE_HOME/mumblelink/mumblelink.log` (falling back to
+//! `~/.local/state`), which is rotated once it grows past 1 MiB. The level is
+//! set with the `log` config key or `MUMBLELINK_LOG`, and defaults to `warn`.
+//!
+//! Most failures happen on every frame, so warnings and errors are rate
+//! limited per call site: a message is logged at most every ten seconds, along
+//! with how many times it was suppressed in between.
+
+use std::collections::HashMap;
+use std::env;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use log::{Level, LevelFilter, Log, Metadata, Record};
+
+use crate::config::Config;
+
+const DEFAULT_LEVEL: LevelFilter = LevelFilter::Warn;
+/// How often the same warning or error may be logged.
+const RATE_LIMIT: Duration = Duration::from_secs(10);
+/// The size after which the log file is rotated.
+const MAX_FILE_SIZE: u64 = 1024 * 1024;
+/// How many rotated log files are kept.
+const KEEP_FILES: u32 = 3;
+
+static LOGGER: OnceLock<Logger> = OnceLock::new();
+
+/// Install the logger, with the level taken from `MUMBLELINK_LOG`. Does
+/// nothing if it is already installed.
+pub fn init() {
+    let mut installed = false;
+    let logger = LOGGER.get_or_init(|| {
+        installed = true;
+        Logger {
+            state: Mutex::new(State {
+                file: None,
+                repeats: HashMap::new(),
+            }),
+        }
+    });
+    if installed && log::set_logger(logger).is_ok() {
+        let level = env::var("MUMBLELINK_LOG")
+            .ok()
+            .and_then(|level| LevelFilter::from_str(&level).ok())
+            .unwrap_or(DEFAULT_LEVEL);
+        log::set_max_level(level);
+    }
+}
+
+/// Apply the logging settings from the configuration.
+pub fn configure(config: &Config) {
+    init();
+    log::set_max_level(config.log);
+    let logger = match LOGGER.get() {
+        Some(logger) => logger,
+        None => return,
+    };
+    let mut state = logger.state.lock().unwrap_or_else(|e| e.into_inner());
+    if !config.log_file {
+        state.file = None;
+    } else if state.file.is_none() {
+        match LogFile::open() {
+            Ok(file) => state.file = Some(file),
+            Err(e) => eprintln!("Mumble Error: Unable to open log file: {}", e),
+        }
+    }
+}
+
+struct Logger {
+    state: Mutex<State>,
+}
+
+struct State {
+    file: Option<LogFile>,
+    /// When each rate limited call site last logged, and how many messages
+    /// it suppressed since.
+    repeats: HashMap<(&'static str, u32), (Instant, u32)>,
+}
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= log::max_level()
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+
+        let mut suppressed = 0;
+        if record.level() <= Level::Warn {
+            if let (Some(file), Some(line)) = (record.file_static(), record.line()) {
+                let now = Instant::now();
+                match state.repeats.get_mut(&(file, line)) {
+                    Some((at, count)) if now.duration_since(*at) < RATE_LIMIT => {
+                        *count += 1;
+                        return;
+                    }
+                    Some((at, count)) => {
+                        suppressed = *count;
+                        *at = now;
+                        *count = 0;
+                    }
+                    None => {
+                        state.repeats.insert((file, line), (now, 0));
+                    }
+                }
+            }
+        }
+
+        let mut line = format!(
+            "{} {:<5} {}: {}",
+            timestamp(SystemTime::now()),
+            record.level(),
+            record.target(),
+            record.args()
+        );
+        if suppressed > 0 {
+            line.push_str(&format!(" ({} similar messages suppressed)", suppressed));
+        }
+
+        eprintln!("{}", line);
+        if let Some(ref mut file) = state.file {
+            if let Err(e) = file.write_line(&line) {
+                eprintln!("Mumble Error: Unable to write log file: {}", e);
+                state.file = None;
+            }
+        }
+    }
+
+    fn flush(&self) {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(ref mut file) = state.file {
+            let _ = file.file.flush();
+        }
+    }
+}
+
+struct LogFile {
+    path: PathBuf,
+    file: File,
+    len: u64,
+}
+
+impl LogFile {
+    fn open() -> io::Result<LogFile> {
+        let dir = state_dir()
+            .ok_or_else(|| io::Error::other("neither XDG_STATE_HOME nor HOME is set"))?
+            .join("mumblelink");
+        fs::create_dir_all(&dir)?;
+        let path = dir.join("mumblelink.log");
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        let len = file.metadata()?.len();
+        Ok(LogFile { path, file, len })
+    }
+
+    fn write_line(&mut self, line: &str) -> io::Result<()> {
+        if self.len >= MAX_FILE_SIZE {
+            self.rotate()?;
+        }
+        writeln!(self.file, "{}", line)?;
+        self.len += line.len() as u64 + 1;
+        Ok(())
+    }
+
+    /// Move `mumblelink.log` to `mumblelink.log.1`, `.1` to `.2` and so on,
+    /// dropping the oldest, and start a new file.
+    fn rotate(&mut self) -> io::Result<()> {
+        let rotated = |n: u32| {
+            let mut path = self.path.clone().into_os_string();
+            path.push(format!(".{}", n));
+            PathBuf::from(path)
+        };
+        for n in (1..KEEP_FILES).rev() {
+            let _ = fs::rename(rotated(n), rotated(n + 1));
+        }
+        fs::rename(&self.path, rotated(1))?;
+        self.file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        self.len = 0;
+        Ok(())
+    }
+}
+
+fn state_dir() -> Option<PathBuf> {
+    match env::var_os("XDG_STATE_HOME") {
+        Some(dir) if !dir.is_empty() => Some(PathBuf::from(dir)),
+        _ => Some(
+            PathBuf::from(env::var_os("HOME")?)
+                .join(".local")
+                .join("state"),
+        ),
+    }
+}
+
+/// Format a time as an RFC 3339 timestamp in UTC, with millisecond precision.
+fn timestamp(time: SystemTime) -> String {
+    let since_epoch = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let secs = since_epoch.as_secs();
+    let (days, secs_of_day) = (secs / 86400, secs % 86400);
+
+    // Convert days since the epoch to a civil date, after Howard Hinnant's
+    // `civil_from_days`.
+    let z = days as i64 + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z",
+        year,
+        month,
+        day,
+        secs_of_day / 3600,
+        secs_of_day / 60 % 60,
+        secs_of_day % 60,
+        since_epoch.subsec_millis()
+    )
+}
+
+#[test]
+fn test_timestamp() {
+    let at = |secs| timestamp(UNIX_EPOCH + Duration::from_millis(secs));
+    assert_eq!(at(0), "1970-01-01T00:00:00.000Z");
+    assert_eq!(at(951_782_400_000), "2000-02-29T00:00:00.000Z");
+    assert_eq!(at(1_792_314_245_123), "2026-10-18T09:04:05.123Z");
+}
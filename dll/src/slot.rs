@@ -0,0 +1,81 @@
+//! A global holder for the link which keeps the per-frame path cheap.
+//!
+//! `update` runs on the render thread every frame, so it only ever tries to
+//! take the lock: uncontended that is a single atomic compare-and-swap, and if
+//! another thread is busy (re)initializing the link, the frame is skipped
+//! rather than stalling the game. Everything else waits for the lock as usual.
+
+use std::sync::{Mutex, MutexGuard, TryLockError};
+
+/// Why `Slot::try_with` didn't run.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Unavailable {
+    /// Nothing has been put in the slot yet.
+    Empty,
+    /// Another thread is using the value.
+    Busy,
+}
+
+pub struct Slot<T> {
+    value: Mutex<Option<T>>,
+}
+
+impl<T> Slot<T> {
+    pub const fn new() -> Slot<T> {
+        Slot {
+            value: Mutex::new(None),
+        }
+    }
+
+    /// Run `f` on the value, waiting for other threads to finish with it.
+    pub fn with<R>(&self, f: impl FnOnce(&mut Option<T>) -> R) -> R {
+        f(&mut self.lock())
+    }
+
+    /// Run `f` on the value if there is one and no other thread is using it.
+    /// Never blocks.
+    pub fn try_with<R>(&self, f: impl FnOnce(&mut T) -> R) -> Result<R, Unavailable> {
+        let mut guard = match self.value.try_lock() {
+            Ok(guard) => guard,
+            // A panic while holding the lock is caught at the JNI boundary;
+            // the link itself is still usable.
+            Err(TryLockError::Poisoned(e)) => e.into_inner(),
+            Err(TryLockError::WouldBlock) => return Err(Unavailable::Busy),
+        };
+        match *guard {
+            Some(ref mut value) => Ok(f(value)),
+            None => Err(Unavailable::Empty),
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Option<T>> {
+        self.value.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl<T> Default for Slot<T> {
+    fn default() -> Self {
+        Slot::new()
+    }
+}
+
+#[test]
+fn test_slot() {
+    let slot = Slot::new();
+    assert_eq!(Err(Unavailable::Empty), slot.try_with(|v: &mut i32| *v));
+
+    slot.with(|v| *v = Some(1));
+    assert_eq!(
+        Ok(2),
+        slot.try_with(|v| {
+            *v += 1;
+            *v
+        })
+    );
+
+    slot.with(|_| {
+        std::thread::scope(|s| {
+            s.spawn(|| assert_eq!(Err(Unavailable::Busy), slot.try_with(|v| *v)));
+        });
+    });
+}
@@ -0,0 +1,724 @@
+use std::env;
+use std::fs::OpenOptions;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{self, AtomicU32, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::{cmp, fmt, io, ptr};
+
+use super::{imp, Error, ErrorCode};
+
+/// Identifies the object behind a segment, such as the device and inode of a
+/// file, so a link can tell when its name has come to refer to another one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SegmentId(pub u64, pub u64);
+
+/// Where the link data is stored.
+///
+/// `ShmBackend` is the shared memory Mumble reads, and what `MumbleLink::new()`
+/// and friends use. `FileBackend` and `MemoryBackend` point the library
+/// somewhere else, for testing or for tools which read the data themselves.
+pub trait Backend: Send {
+    /// A name for the location, used in errors and logs.
+    fn name(&self) -> String;
+
+    /// Open the existing segment, using at most `size` bytes of it. A smaller
+    /// segment is opened as far as it goes; see `Segment::size()`.
+    fn open(&self, size: usize, writable: bool) -> Result<Box<dyn Segment>, Error>;
+
+    /// Create the segment, `size` bytes long and zeroed, the way Mumble does.
+    /// Shared memory which already exists, such as the one of a running
+    /// Mumble, is left alone and an error returned instead.
+    fn create(&self, size: usize) -> Result<Box<dyn Segment>, Error>;
+
+    /// The identity of the segment currently behind the name, or `None` if
+    /// there is none or the backend can't tell.
+    fn current_id(&self) -> Option<SegmentId> {
+        None
+    }
+}
+
+/// An open segment, as returned by a `Backend`.
+pub trait Segment: Send {
+    /// How many bytes of the segment are usable. Writes past the end are
+    /// dropped, and reads past it leave the rest of the buffer alone.
+    fn size(&self) -> usize;
+
+    /// Copy the start of the segment into `buf`.
+    fn read(&self, buf: &mut [u8]);
+
+    /// Copy `data` into the segment, starting `offset` bytes in.
+    fn write(&mut self, offset: usize, data: &[u8]);
+
+    /// Store `value` at `offset` only after everything written before, so a
+    /// reader which sees the new value also sees those writes. Used for the
+    /// tick, which tells Mumble a new frame is complete.
+    ///
+    /// The default is a release fence followed by a plain `write()`, which
+    /// only orders the writes for readers that synchronize with `write()`
+    /// some other way, such as through the lock `MemoryBackend` takes.
+    /// Segments whose memory another process reads directly must override
+    /// it with an atomic store.
+    fn publish(&mut self, offset: usize, value: u32) {
+        atomic::fence(Ordering::Release);
+        self.write(offset, &value.to_ne_bytes());
+    }
+
+    /// The identity of the object this segment maps, if it has one. A link
+    /// reopens its segment once this no longer matches the backend's
+    /// `current_id()`, such as when Mumble has been restarted.
+    fn id(&self) -> Option<SegmentId> {
+        None
+    }
+}
+
+/// Whether `segment` has been replaced since it was opened from `backend`.
+pub(crate) fn is_stale(backend: &dyn Backend, segment: &dyn Segment) -> bool {
+    match segment.id() {
+        Some(id) => backend.current_id() != Some(id),
+        None => false,
+    }
+}
+
+/// A memory mapping, shared by the shared memory and file backends.
+struct Mapped {
+    map: imp::Map,
+}
+
+impl Segment for Mapped {
+    fn size(&self) -> usize {
+        self.map.len
+    }
+
+    fn read(&self, buf: &mut [u8]) {
+        let len = cmp::min(buf.len(), self.map.len);
+        unsafe {
+            ptr::copy_nonoverlapping(self.map.ptr as *const u8, buf.as_mut_ptr(), len);
+        }
+    }
+
+    fn write(&mut self, offset: usize, data: &[u8]) {
+        if offset >= self.map.len {
+            return;
+        }
+        let len = cmp::min(data.len(), self.map.len - offset);
+        unsafe {
+            let dest = (self.map.ptr as *mut u8).add(offset);
+            ptr::copy_nonoverlapping(data.as_ptr(), dest, len);
+        }
+    }
+
+    fn publish(&mut self, offset: usize, value: u32) {
+        if !offset.is_multiple_of(4) || offset >= self.map.len || self.map.len - offset < 4 {
+            atomic::fence(Ordering::Release);
+            return self.write(offset, &value.to_ne_bytes());
+        }
+        // The mapping is page-aligned, so the field is aligned too
+        let field = unsafe { &*((self.map.ptr as *mut u8).add(offset) as *const AtomicU32) };
+        field.store(value, Ordering::Release);
+    }
+
+    fn id(&self) -> Option<SegmentId> {
+        self.map.id().map(|(dev, ino)| SegmentId(dev, ino))
+    }
+}
+
+// The mapping is only accessed through `&self` and `&mut self`.
+unsafe impl Send for Mapped {}
+
+/// The shared memory Mumble creates: `/MumbleLink.<uid>` in POSIX shared
+/// memory, or the `MumbleLink` file mapping on Windows.
+///
+/// On Unix-likes, the segment must belong to the current user, unless
+/// another owner is given explicitly, and must not be accessible by anyone
+/// else, the way Mumble creates it. Otherwise other users could read the
+/// player's position and identity, or feed them to Mumble.
+#[derive(Debug, Clone)]
+pub struct ShmBackend {
+    name: String,
+    verify: bool,
+    /// Who must own the segment, if not the current user.
+    owner: Option<u32>,
+}
+
+impl ShmBackend {
+    /// The segment Mumble uses for the current user.
+    pub fn new() -> ShmBackend {
+        ShmBackend::named(&imp::default_name())
+    }
+
+    /// The segment Mumble uses when run by the user `uid`, which must own it.
+    /// Only the current user's segment exists on Windows.
+    pub fn for_uid(uid: u32) -> ShmBackend {
+        ShmBackend::named(&imp::name_for_uid(uid)).owned_by(uid)
+    }
+
+    /// A segment with another name. On Unix-likes, the name should start
+    /// with a slash.
+    pub fn named(name: &str) -> ShmBackend {
+        ShmBackend {
+            name: name.to_owned(),
+            verify: true,
+            owner: None,
+        }
+    }
+
+    /// Expect the segment to belong to the user `uid` instead of the current
+    /// user, such as when Mumble runs as another user on purpose.
+    pub fn owned_by(mut self, uid: u32) -> ShmBackend {
+        self.owner = Some(uid);
+        self
+    }
+
+    /// Use the segment no matter who owns it or who else can access it, for
+    /// setups where Mumble and the game run as different users on purpose.
+    pub fn skip_checks(mut self) -> ShmBackend {
+        self.verify = false;
+        self
+    }
+}
+
+impl Default for ShmBackend {
+    fn default() -> Self {
+        ShmBackend::new()
+    }
+}
+
+impl Backend for ShmBackend {
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    fn open(&self, size: usize, writable: bool) -> Result<Box<dyn Segment>, Error> {
+        let owner = if self.verify {
+            Some(self.owner.unwrap_or_else(imp::current_user))
+        } else {
+            None
+        };
+        let map = imp::Map::open(&self.name, size, writable, owner)?;
+        Ok(Box::new(Mapped { map }))
+    }
+
+    fn create(&self, size: usize) -> Result<Box<dyn Segment>, Error> {
+        let map = imp::Map::create(&self.name, size)?;
+        Ok(Box::new(Mapped { map }))
+    }
+
+    fn current_id(&self) -> Option<SegmentId> {
+        imp::current_id(&self.name).map(|(dev, ino)| SegmentId(dev, ino))
+    }
+}
+
+/// A memory-mapped regular file at a custom path.
+///
+/// The file is checked like `ShmBackend` does.
+#[derive(Debug, Clone)]
+pub struct FileBackend {
+    path: PathBuf,
+    verify: bool,
+    owner: Option<u32>,
+}
+
+impl FileBackend {
+    pub fn new<P: AsRef<Path>>(path: P) -> FileBackend {
+        FileBackend {
+            path: path.as_ref().to_owned(),
+            verify: true,
+            owner: None,
+        }
+    }
+
+    /// See `ShmBackend::owned_by()`.
+    pub fn owned_by(mut self, uid: u32) -> FileBackend {
+        self.owner = Some(uid);
+        self
+    }
+
+    /// See `ShmBackend::skip_checks()`.
+    pub fn skip_checks(mut self) -> FileBackend {
+        self.verify = false;
+        self
+    }
+}
+
+impl Backend for FileBackend {
+    fn name(&self) -> String {
+        self.path.display().to_string()
+    }
+
+    fn open(&self, size: usize, writable: bool) -> Result<Box<dyn Segment>, Error> {
+        let name = self.name();
+        let err = |e| Error::new(ErrorCode::ShmOpen, &name, e);
+        let file = OpenOptions::new()
+            .read(true)
+            .write(writable)
+            .open(&self.path)
+            .map_err(err)?;
+        let owner = if self.verify {
+            Some(self.owner.unwrap_or_else(imp::current_user))
+        } else {
+            None
+        };
+        let map = imp::Map::file(file, &name, size, writable, owner)?;
+        Ok(Box::new(Mapped { map }))
+    }
+
+    fn create(&self, size: usize) -> Result<Box<dyn Segment>, Error> {
+        let name = self.name();
+        let err = |e| Error::new(ErrorCode::ShmOpen, &name, e);
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(&self.path)
+            .map_err(err)?;
+        file.set_len(size as u64).map_err(err)?;
+        #[cfg(unix)]
+        {
+            use std::fs::Permissions;
+            use std::os::unix::fs::PermissionsExt;
+            file.set_permissions(Permissions::from_mode(0o600))
+                .map_err(err)?;
+        }
+        let map = imp::Map::file(file, &name, size, true, Some(imp::current_user()))?;
+        Ok(Box::new(Mapped { map }))
+    }
+
+    #[cfg(unix)]
+    fn current_id(&self) -> Option<SegmentId> {
+        use std::os::unix::fs::MetadataExt;
+        let metadata = self.path.metadata().ok()?;
+        Some(SegmentId(metadata.dev(), metadata.ino()))
+    }
+}
+
+/// Where to look for the link instead of the usual places.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Overrides {
+    /// The name of the shared memory segment, or a path to a file.
+    pub segment: Option<String>,
+    /// The user whose Mumble to link to, if not the current one. The segment
+    /// is expected to belong to them.
+    pub uid: Option<u32>,
+    /// Use the segment even if it belongs to another user or other users can
+    /// access it. See `ShmBackend::skip_checks()`.
+    pub skip_checks: bool,
+}
+
+impl Overrides {
+    /// Read the overrides from the `MUMBLELINK_SEGMENT`, `MUMBLELINK_UID` and
+    /// `MUMBLELINK_SKIP_CHECKS` environment variables. Invalid values are
+    /// logged and ignored.
+    pub fn from_env() -> Overrides {
+        let var = |name| env::var(name).ok().filter(|value| !value.is_empty());
+        Overrides {
+            segment: var("MUMBLELINK_SEGMENT"),
+            uid: var("MUMBLELINK_UID").and_then(|uid| match uid.parse() {
+                Ok(uid) => Some(uid),
+                Err(e) => {
+                    warn!("Ignoring MUMBLELINK_UID={:?}: {}", uid, e);
+                    None
+                }
+            }),
+            skip_checks: matches!(
+                var("MUMBLELINK_SKIP_CHECKS").as_ref().map(|v| &v[..]),
+                Some("true") | Some("yes") | Some("on") | Some("1")
+            ),
+        }
+    }
+}
+
+/// Tries a list of backends in order, and uses the first one which opens.
+///
+/// This is what `MumbleLink::new()` and friends use, with the candidates from
+/// `from_env()`.
+pub struct ProbingBackend {
+    candidates: Vec<Box<dyn Backend>>,
+    /// The candidate which was opened last.
+    used: Mutex<Option<usize>>,
+}
+
+impl ProbingBackend {
+    /// Try each of `candidates` in turn. New segments are created in the first
+    /// one.
+    ///
+    /// # Panics
+    ///
+    /// If there are no candidates.
+    pub fn new(candidates: Vec<Box<dyn Backend>>) -> ProbingBackend {
+        assert!(!candidates.is_empty(), "no candidate backends");
+        ProbingBackend {
+            candidates,
+            used: Mutex::new(None),
+        }
+    }
+
+    /// The places Mumble's link is likely to be, unless overridden with
+    /// environment variables. See `Overrides::from_env()`.
+    pub fn from_env() -> ProbingBackend {
+        ProbingBackend::with_overrides(&Overrides::from_env())
+    }
+
+    /// The places Mumble's link is likely to be.
+    ///
+    /// With a `segment`, only that is tried: a path to a file, such as a
+    /// shared memory segment visible through a container's or sandbox's
+    /// filesystem, or otherwise the name of a shared memory segment.
+    /// Otherwise, the segment of the user `uid` (by default the current user)
+    /// is tried first, followed by the other places Mumble might have put it.
+    ///
+    /// Every candidate must belong to the current user, except for the ones
+    /// given explicitly, which may belong to `uid` or to the user a `segment`
+    /// is named after, and the one of the user who ran `sudo` or `pkexec`,
+    /// which belongs to them.
+    pub fn with_overrides(overrides: &Overrides) -> ProbingBackend {
+        let shm = |name: &str, owner: Option<u32>| -> Box<dyn Backend> {
+            let mut backend = ShmBackend::named(name);
+            if let Some(owner) = owner {
+                backend = backend.owned_by(owner);
+            }
+            if overrides.skip_checks {
+                backend = backend.skip_checks();
+            }
+            Box::new(backend)
+        };
+        let candidates: Vec<Box<dyn Backend>> = match overrides.segment {
+            Some(ref segment) if is_path(segment) => {
+                let mut backend = FileBackend::new(segment);
+                if let Some(owner) = overrides.uid {
+                    backend = backend.owned_by(owner);
+                }
+                if overrides.skip_checks {
+                    backend = backend.skip_checks();
+                }
+                vec![Box::new(backend)]
+            }
+            Some(ref segment) => {
+                let name = if cfg!(windows) || segment.starts_with('/') {
+                    segment.clone()
+                } else {
+                    format!("/{}", segment)
+                };
+                let owner = overrides.uid.or_else(|| imp::uid_of(&name));
+                vec![shm(&name, owner)]
+            }
+            None => imp::candidates(overrides.uid)
+                .iter()
+                .map(|(name, owner)| shm(name, *owner))
+                .collect(),
+        };
+        ProbingBackend::new(candidates)
+    }
+
+    /// The names of the candidates, in the order they are tried.
+    pub fn candidates(&self) -> Vec<String> {
+        self.candidates.iter().map(|c| c.name()).collect()
+    }
+
+    /// The name of the candidate which was opened last, if any.
+    pub fn used(&self) -> Option<String> {
+        lock(&self.used).map(|i| self.candidates[i].name())
+    }
+}
+
+fn is_missing(err: &Error) -> bool {
+    err.os_error().kind() == io::ErrorKind::NotFound
+}
+
+/// Whether a segment override names a file rather than a shared memory
+/// segment.
+fn is_path(segment: &str) -> bool {
+    if cfg!(windows) {
+        segment.contains('\\') || segment.contains('/')
+    } else {
+        segment.chars().skip(1).any(|c| c == '/')
+    }
+}
+
+impl fmt::Debug for ProbingBackend {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ProbingBackend")
+            .field("candidates", &self.candidates())
+            .field("used", &self.used())
+            .finish()
+    }
+}
+
+impl Backend for ProbingBackend {
+    /// The candidate which was opened last, or otherwise all of them.
+    fn name(&self) -> String {
+        self.used().unwrap_or_else(|| self.candidates().join(", "))
+    }
+
+    fn open(&self, size: usize, writable: bool) -> Result<Box<dyn Segment>, Error> {
+        let mut first: Option<Error> = None;
+        for (i, candidate) in self.candidates.iter().enumerate() {
+            match candidate.open(size, writable) {
+                Ok(segment) => {
+                    *lock(&self.used) = Some(i);
+                    return Ok(segment);
+                }
+                Err(e) => {
+                    debug!("Mumble link is not at {}: {}", candidate.name(), e);
+                    // Report the first error, unless a later candidate exists
+                    // but can't be opened, e.g. as it belongs to another user.
+                    let better = match first {
+                        None => true,
+                        Some(ref first) => is_missing(first) && !is_missing(&e),
+                    };
+                    if better {
+                        first = Some(e);
+                    }
+                }
+            }
+        }
+        *lock(&self.used) = None;
+        Err(first.expect("no candidate backends"))
+    }
+
+    fn create(&self, size: usize) -> Result<Box<dyn Segment>, Error> {
+        let segment = self.candidates[0].create(size)?;
+        *lock(&self.used) = Some(0);
+        Ok(segment)
+    }
+
+    fn current_id(&self) -> Option<SegmentId> {
+        let used = *lock(&self.used);
+        used.and_then(|i| self.candidates[i].current_id())
+    }
+}
+
+type Buffer = Arc<Mutex<Vec<u8>>>;
+
+/// A buffer in this process, for testing.
+///
+/// Clones share the same segment. Like a machine where Mumble isn't running,
+/// there is no segment until it is created with `LinkHost` or
+/// `Backend::create()`, and `remove()` takes it away again.
+#[derive(Clone, Default)]
+pub struct MemoryBackend {
+    current: Arc<Mutex<Option<Buffer>>>,
+}
+
+impl MemoryBackend {
+    pub fn new() -> MemoryBackend {
+        MemoryBackend::default()
+    }
+
+    /// Remove the segment, like Mumble does when it exits. Open segments keep
+    /// their buffer, but nothing else will see it.
+    pub fn remove(&self) {
+        *lock(&self.current) = None;
+    }
+}
+
+impl fmt::Debug for MemoryBackend {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MemoryBackend")
+            .field("exists", &lock(&self.current).is_some())
+            .finish()
+    }
+}
+
+impl Backend for MemoryBackend {
+    fn name(&self) -> String {
+        "memory".to_owned()
+    }
+
+    fn open(&self, _size: usize, _writable: bool) -> Result<Box<dyn Segment>, Error> {
+        let buffer = match *lock(&self.current) {
+            Some(ref buffer) => buffer.clone(),
+            None => {
+                let err = io::Error::from(io::ErrorKind::NotFound);
+                return Err(Error::new(ErrorCode::ShmOpen, &self.name(), err));
+            }
+        };
+        Ok(Box::new(MemorySegment { buffer }))
+    }
+
+    fn create(&self, size: usize) -> Result<Box<dyn Segment>, Error> {
+        let buffer = Arc::new(Mutex::new(vec![0; size]));
+        *lock(&self.current) = Some(buffer.clone());
+        Ok(Box::new(MemorySegment { buffer }))
+    }
+
+    fn current_id(&self) -> Option<SegmentId> {
+        lock(&self.current).as_ref().map(buffer_id)
+    }
+}
+
+struct MemorySegment {
+    buffer: Buffer,
+}
+
+impl Segment for MemorySegment {
+    fn size(&self) -> usize {
+        lock(&self.buffer).len()
+    }
+
+    fn read(&self, buf: &mut [u8]) {
+        let buffer = lock(&self.buffer);
+        let len = cmp::min(buf.len(), buffer.len());
+        buf[..len].copy_from_slice(&buffer[..len]);
+    }
+
+    fn write(&mut self, offset: usize, data: &[u8]) {
+        let mut buffer = lock(&self.buffer);
+        let start = cmp::min(offset, buffer.len());
+        let len = cmp::min(data.len(), buffer.len() - start);
+        buffer[start..start + len].copy_from_slice(&data[..len]);
+    }
+
+    fn id(&self) -> Option<SegmentId> {
+        Some(buffer_id(&self.buffer))
+    }
+}
+
+/// Buffers are identified by their address, which can't be reused while a
+/// segment still holds on to the buffer.
+fn buffer_id(buffer: &Buffer) -> SegmentId {
+    SegmentId(Arc::as_ptr(buffer) as usize as u64, 0)
+}
+
+pub(crate) fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+#[test]
+fn test_memory_restart() {
+    use super::{LinkHost, Position, SharedLink, Status, RETRY_INTERVAL};
+
+    let backend = MemoryBackend::new();
+    let mut link = SharedLink::with_backend(Box::new(backend.clone()), "Game", "");
+    assert!(matches!(link.status(), Status::Closed(_)));
+
+    // Mumble starts
+    let mut host = LinkHost::with_backend(&backend).unwrap();
+    link.checked_at -= RETRY_INTERVAL;
+    link.update(Position::default(), Position::default());
+    assert!(matches!(link.status(), Status::Active));
+    assert!(host.poll().is_some());
+
+    // Mumble restarts, and the link follows it to the new segment
+    backend.remove();
+    let mut host = LinkHost::with_backend(&backend).unwrap();
+    link.checked_at -= RETRY_INTERVAL;
+    link.update(Position::default(), Position::default());
+    assert!(matches!(link.status(), Status::Active));
+    assert_eq!("Game", host.poll().unwrap().name);
+}
+
+#[test]
+fn test_file() {
+    use super::{LinkHost, LinkReader, MumbleLink, Position, LINK_SIZE};
+    use std::{env, fs, process};
+
+    let path = env::temp_dir().join(format!("mumble-link-test-file.{}", process::id()));
+    let backend = FileBackend::new(&path);
+    assert!(MumbleLink::with_backend(Box::new(backend.clone()), "Game", "").is_err());
+
+    // Too small to map safely
+    fs::write(&path, [0; 16]).unwrap();
+    assert!(LinkReader::with_backend(Box::new(backend.clone())).is_err());
+
+    let _host = LinkHost::with_backend(&backend).unwrap();
+    assert_eq!(LINK_SIZE as u64, fs::metadata(&path).unwrap().len());
+    let mut link = MumbleLink::with_backend(Box::new(backend.clone()), "Game", "").unwrap();
+    link.update(Position::default(), Position::default());
+    let reader = LinkReader::with_backend(Box::new(backend.clone())).unwrap();
+    assert_eq!("Game", reader.read().name);
+
+    // Writes past the end are dropped
+    let mut segment = backend.open(LINK_SIZE, true).unwrap();
+    segment.write(LINK_SIZE - 2, &[1; 4]);
+    segment.write(usize::MAX, &[1; 4]);
+    segment.publish(usize::MAX - 3, 1);
+    let mut buf = vec![0; LINK_SIZE + 4];
+    segment.read(&mut buf);
+    assert_eq!([1, 1, 0, 0], buf[LINK_SIZE - 2..LINK_SIZE + 2]);
+    assert!(!reader.is_stale());
+
+    drop(link);
+    fs::remove_file(&path).unwrap();
+    assert!(reader.is_stale());
+}
+
+#[test]
+fn test_probing() {
+    let missing = ShmBackend::named(&imp::test_name("probe-missing"));
+    let memory = MemoryBackend::new();
+    memory.create(64).unwrap();
+    let probe = ProbingBackend::new(vec![Box::new(missing), Box::new(memory.clone())]);
+    assert_eq!(None, probe.used());
+
+    let segment = probe.open(64, true).unwrap();
+    assert_eq!(Some("memory".to_owned()), probe.used());
+    assert_eq!("memory", probe.name());
+    assert!(!is_stale(&probe, &*segment));
+
+    // Nothing left: the first candidate's error is reported
+    memory.remove();
+    assert!(is_stale(&probe, &*segment));
+    let err = match probe.open(64, true) {
+        Ok(_) => panic!("opened a removed segment"),
+        Err(e) => e,
+    };
+    assert_eq!(imp::test_name("probe-missing"), err.segment());
+    assert_eq!(None, probe.used());
+}
+
+#[cfg(not(windows))]
+#[test]
+fn test_overrides() {
+    let names = |segment: Option<&str>, uid| {
+        let overrides = Overrides {
+            segment: segment.map(str::to_owned),
+            uid,
+            skip_checks: false,
+        };
+        ProbingBackend::with_overrides(&overrides).candidates()
+    };
+    assert_eq!(
+        vec!["/tmp/mumble/MumbleLink.42"],
+        names(Some("/tmp/mumble/MumbleLink.42"), None)
+    );
+    assert_eq!(
+        vec!["/MumbleLink.42"],
+        names(Some("MumbleLink.42"), Some(7))
+    );
+    assert_eq!(vec!["/MumbleLink.42"], names(Some("/MumbleLink.42"), None));
+    assert_eq!("/MumbleLink.7", names(None, Some(7))[0]);
+    assert_eq!(vec!["/é"], names(Some("é"), None));
+    assert_eq!(Some(42), ShmBackend::for_uid(42).owner);
+    assert_eq!(None, ShmBackend::named("/MumbleLink.42").owner);
+    assert_eq!(vec!["é/MumbleLink"], names(Some("é/MumbleLink"), None));
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn test_sudo() {
+    use super::{ErrorKind, LINK_SIZE};
+    use std::os::unix::fs::chown;
+    use std::process;
+
+    // Run with `sudo` by a uid nobody has
+    let uid = 4_100_000_000 + process::id();
+    let name = imp::name_for_uid(uid);
+    let saved = env::var_os("SUDO_UID");
+    env::set_var("SUDO_UID", uid.to_string());
+    let probe = ProbingBackend::with_overrides(&Overrides::default());
+    match saved {
+        Some(saved) => env::set_var("SUDO_UID", saved),
+        None => env::remove_var("SUDO_UID"),
+    }
+    let i = probe.candidates().iter().position(|c| *c == name).unwrap();
+
+    // Their segment is expected to belong to them, and not to us
+    let _segment = ShmBackend::named(&name).create(LINK_SIZE).unwrap();
+    let err = probe.candidates[i].open(LINK_SIZE, true).err().unwrap();
+    assert_eq!(ErrorKind::Insecure, err.kind());
+    if imp::current_user() == 0 {
+        chown(format!("/dev/shm{}", name), Some(uid), None).unwrap();
+        assert!(probe.candidates[i].open(LINK_SIZE, true).is_ok());
+    }
+}
@@ -0,0 +1,323 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use super::backend::lock;
+use super::{Position, SharedLink};
+
+/// Samples further apart than this aren't moving together, such as when the
+/// game was paused in between, so the positions aren't extrapolated.
+const MAX_SAMPLE_GAP: Duration = Duration::from_millis(250);
+
+/// How long the driver keeps publishing after the last sample, by default.
+const DEFAULT_IDLE: Duration = Duration::from_secs(1);
+
+/// Publishes a `SharedLink` at a fixed rate on its own thread.
+///
+/// Games update at whatever their frame rate is, which may be 30 times a
+/// second or 500. Instead of calling `update()` every frame, pass each frame's
+/// positions to `sample()`, from any thread, and the driver publishes them
+/// `rate` times a second. Between samples, the positions are extrapolated from
+/// the last two, so they keep moving smoothly when the game is slower than the
+/// driver. Once the samples stop, the positions soon come to rest, and after
+/// `set_idle()` without any, the driver stops publishing until the next one.
+/// The tick then stands still, so Mumble, or a watchdog, sees that the game
+/// froze or went away.
+///
+/// Everything else, such as the context or the status, goes through
+/// `with_link()`.
+pub struct LinkDriver {
+    shared: Arc<Shared>,
+    thread: Option<JoinHandle<()>>,
+}
+
+struct Shared {
+    link: Mutex<SharedLink>,
+    samples: Mutex<Samples>,
+    idle: Mutex<Duration>,
+    stop: AtomicBool,
+}
+
+/// The last two samples, the latest first.
+type Samples = [Option<Sample>; 2];
+
+#[derive(Clone, Copy)]
+struct Sample {
+    at: Instant,
+    avatar: Position,
+    camera: Position,
+}
+
+impl LinkDriver {
+    /// Start publishing `link` `rate` times a second, from the first sample
+    /// on.
+    ///
+    /// # Panics
+    ///
+    /// If `rate` is zero.
+    pub fn new(link: SharedLink, rate: u32) -> LinkDriver {
+        assert!(rate > 0, "link driver rate is zero");
+        let interval = Duration::from_secs(1) / rate;
+        let shared = Arc::new(Shared {
+            link: Mutex::new(link),
+            samples: Mutex::new([None, None]),
+            idle: Mutex::new(DEFAULT_IDLE),
+            stop: AtomicBool::new(false),
+        });
+        let thread = {
+            let shared = shared.clone();
+            thread::Builder::new()
+                .name("mumble-link-driver".to_owned())
+                .spawn(move || run(&shared, interval))
+                .expect("failed to spawn the link driver thread")
+        };
+        LinkDriver {
+            shared,
+            thread: Some(thread),
+        }
+    }
+
+    /// The positions as of now, to be published on the next tick.
+    pub fn sample(&self, avatar: Position, camera: Position) {
+        let mut samples = lock(&self.shared.samples);
+        samples[1] = samples[0].take();
+        samples[0] = Some(Sample {
+            at: Instant::now(),
+            avatar,
+            camera,
+        });
+    }
+
+    /// Stop publishing once there was no sample for `idle`, one second by
+    /// default. This is never shorter than the gap between samples which are
+    /// still extrapolated.
+    pub fn set_idle(&self, idle: Duration) {
+        *lock(&self.shared.idle) = idle.max(MAX_SAMPLE_GAP);
+    }
+
+    /// Use the link, such as to set the context. The driver waits until `f`
+    /// returns before publishing again.
+    pub fn with_link<R, F: FnOnce(&mut SharedLink) -> R>(&self, f: F) -> R {
+        f(&mut lock(&self.shared.link))
+    }
+
+    /// Stop publishing, and hand back the link.
+    pub fn stop(self) -> SharedLink {
+        let shared = self.shared.clone();
+        drop(self);
+        match Arc::try_unwrap(shared) {
+            Ok(shared) => shared.link.into_inner().unwrap_or_else(|e| e.into_inner()),
+            Err(_) => unreachable!("the driver thread has exited"),
+        }
+    }
+
+    fn halt(&mut self) {
+        self.shared.stop.store(true, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            thread.thread().unpark();
+            let _ = thread.join();
+        }
+    }
+}
+
+impl Drop for LinkDriver {
+    fn drop(&mut self) {
+        self.halt();
+    }
+}
+
+impl std::fmt::Debug for LinkDriver {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("LinkDriver")
+            .field("location", &lock(&self.shared.link).location())
+            .finish()
+    }
+}
+
+fn run(shared: &Shared, interval: Duration) {
+    let mut next = Instant::now();
+    while !shared.stop.load(Ordering::Relaxed) {
+        let now = Instant::now();
+        if now < next {
+            thread::park_timeout(next - now);
+            continue;
+        }
+        // Skip the ticks missed while falling behind instead of catching up
+        next = if now - next > interval {
+            now + interval
+        } else {
+            next + interval
+        };
+
+        let samples = *lock(&shared.samples);
+        // Leave the tick alone until the game samples again
+        let idle = *lock(&shared.idle);
+        let (avatar, camera) = match samples {
+            [Some(last), _] if now.saturating_duration_since(last.at) > idle => continue,
+            [Some(last), Some(previous)] => predict(&previous, &last, now),
+            [Some(last), None] => (last.avatar, last.camera),
+            [None, _] => continue,
+        };
+        lock(&shared.link).update(avatar, camera);
+    }
+}
+
+/// Where the positions are at `now`, continuing in a straight line from
+/// `previous` through `last`. This goes on for at most as long as between the
+/// two samples, after which the positions stay put.
+fn predict(previous: &Sample, last: &Sample, now: Instant) -> (Position, Position) {
+    let between = last.at.duration_since(previous.at);
+    if between.is_zero() || between > MAX_SAMPLE_GAP {
+        return (last.avatar, last.camera);
+    }
+    let between = between.as_secs_f32();
+    let since = now.saturating_duration_since(last.at).as_secs_f32();
+    let t = 1. + (since / between).min(1.);
+    (
+        extrapolate(&previous.avatar, &last.avatar, t),
+        extrapolate(&previous.camera, &last.camera, t),
+    )
+}
+
+/// `t` of the way from `from` to `to`, keeping the direction vectors unit
+/// length. An all-zero position, which disables positional audio, is kept.
+fn extrapolate(from: &Position, to: &Position, t: f32) -> Position {
+    if is_zero(from) || is_zero(to) {
+        return *to;
+    }
+    Position {
+        position: lerp(from.position, to.position, t),
+        front: normalize(lerp(from.front, to.front, t), to.front),
+        top: normalize(lerp(from.top, to.top, t), to.top),
+    }
+}
+
+fn is_zero(position: &Position) -> bool {
+    position
+        .position
+        .iter()
+        .chain(&position.front)
+        .chain(&position.top)
+        .all(|&x| x == 0.)
+}
+
+fn lerp(from: [f32; 3], to: [f32; 3], t: f32) -> [f32; 3] {
+    let mut out = [0.; 3];
+    for i in 0..3 {
+        out[i] = from[i] + (to[i] - from[i]) * t;
+    }
+    out
+}
+
+/// `v` scaled to unit length, or `fallback` if it has none.
+fn normalize(v: [f32; 3], fallback: [f32; 3]) -> [f32; 3] {
+    let len = v.iter().map(|x| x * x).sum::<f32>().sqrt();
+    if len > 0. {
+        [v[0] / len, v[1] / len, v[2] / len]
+    } else {
+        fallback
+    }
+}
+
+#[test]
+fn test_predict() {
+    let start = Instant::now();
+    let ms = |ms: f32| Duration::from_secs_f32(ms / 1024.);
+    let at = |x: f32, at: Instant| Sample {
+        at,
+        avatar: Position {
+            position: [x, 0., 0.],
+            ..Position::default()
+        },
+        camera: Position::default(),
+    };
+    let previous = at(0., start);
+    let last = at(1., start + ms(128.));
+
+    let (avatar, camera) = predict(&previous, &last, start + ms(128.));
+    assert_eq!([1., 0., 0.], avatar.position);
+    assert_eq!([0., 0., 1.], camera.front);
+    let (avatar, _) = predict(&previous, &last, start + ms(192.));
+    assert_eq!([1.5, 0., 0.], avatar.position);
+    // Held after as long again
+    let (avatar, _) = predict(&previous, &last, start + ms(1024.));
+    assert_eq!([2., 0., 0.], avatar.position);
+    // Not at all if the samples are too far apart
+    let (avatar, _) = predict(&previous, &at(1., start + ms(512.)), start + ms(768.));
+    assert_eq!([1., 0., 0.], avatar.position);
+
+    let zero = Position {
+        position: [0.; 3],
+        front: [0.; 3],
+        top: [0.; 3],
+    };
+    let turned = Position {
+        front: [1., 0., 0.],
+        ..Position::default()
+    };
+    let moved = extrapolate(&Position::default(), &turned, 1.5);
+    let len: f32 = moved.front.iter().map(|x| x * x).sum();
+    assert!((len - 1.).abs() < 1e-6);
+    assert_eq!([0.; 3], extrapolate(&turned, &zero, 2.).front);
+}
+
+#[test]
+fn test_driver() {
+    use super::{Backend, LinkedMem, MemoryBackend, LINK_SIZE};
+
+    let backend = MemoryBackend::new();
+    let segment = backend.create(LINK_SIZE).unwrap();
+    let link = SharedLink::with_backend(Box::new(backend), "Game", "");
+    let driver = LinkDriver::new(link, 200);
+    let position = Position {
+        position: [1., 2., 3.],
+        ..Position::default()
+    };
+    driver.sample(position, position);
+
+    // Ticks without any more samples
+    thread::sleep(Duration::from_millis(100));
+    let mem = LinkedMem::read(&*segment);
+    assert!(mem.ui_tick > 5, "tick {}", mem.ui_tick);
+    assert_eq!([1., 2., 3.], mem.avatar.position);
+
+    driver
+        .with_link(|link| link.set_context(b"server"))
+        .unwrap();
+    thread::sleep(Duration::from_millis(20));
+    let link = driver.stop();
+    let tick = LinkedMem::read(&*segment).ui_tick;
+    thread::sleep(Duration::from_millis(20));
+    assert_eq!(tick, LinkedMem::read(&*segment).ui_tick);
+    assert_eq!(b"server", &LinkedMem::read(&*segment).context[..6]);
+    drop(link);
+}
+
+#[test]
+fn test_driver_idle() {
+    use super::{Backend, LinkedMem, MemoryBackend, LINK_SIZE};
+
+    let backend = MemoryBackend::new();
+    let segment = backend.create(LINK_SIZE).unwrap();
+    let link = SharedLink::with_backend(Box::new(backend), "Game", "");
+    let driver = LinkDriver::new(link, 200);
+    driver.set_idle(MAX_SAMPLE_GAP);
+
+    // Nothing before the first sample
+    thread::sleep(Duration::from_millis(50));
+    assert_eq!(0, LinkedMem::read(&*segment).ui_tick);
+
+    // The tick stops once the samples do
+    driver.sample(Position::default(), Position::default());
+    thread::sleep(Duration::from_millis(400));
+    let tick = LinkedMem::read(&*segment).ui_tick;
+    assert!(tick > 5, "tick {}", tick);
+    thread::sleep(Duration::from_millis(50));
+    assert_eq!(tick, LinkedMem::read(&*segment).ui_tick);
+
+    // And goes on with the next one
+    driver.sample(Position::default(), Position::default());
+    thread::sleep(Duration::from_millis(50));
+    assert!(LinkedMem::read(&*segment).ui_tick > tick);
+}
@@ -0,0 +1,206 @@
+use std::fmt::{self, Display};
+use std::{error, io};
+
+/// The error to send to mumble.
+/// See: https://www.mumble.info/documentation/developer/positional-audio/link-plugin/
+#[repr(i32)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ErrorCode {
+    Success = 0,
+    OpenFileMappingW = 1,
+    MapViewOfFile = 2,
+    ShmOpen = 3,
+    MMap = 4,
+    NoMem = 5,
+    Unknown = 6,
+}
+
+impl Display for ErrorCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match *self {
+            ErrorCode::Success => "no error",
+            ErrorCode::OpenFileMappingW => "OpenFileMappingW failed to return a handle",
+            ErrorCode::MapViewOfFile => "MapViewOfFile failed to return a structure",
+            ErrorCode::ShmOpen => "shm_open returned a negative integer",
+            ErrorCode::MMap => "mmap failed to return a structure",
+            ErrorCode::NoMem => "shared memory was not initialized",
+            ErrorCode::Unknown => "unknown Error",
+        })
+    }
+}
+
+impl error::Error for ErrorCode {}
+
+/// What went wrong, where the Mumble `ErrorCode` doesn't tell.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ErrorKind {
+    /// A system call failed; see `Error::os_error()`.
+    System,
+    /// The segment belongs to or is shared with other users. Reported to
+    /// Mumble as `ErrorCode::ShmOpen`.
+    Insecure,
+    /// The segment is too small for the link. Reported to Mumble as
+    /// `ErrorCode::MMap`.
+    TooSmall,
+}
+
+/// An error opening the Mumble link.
+///
+/// Carries the operating system's error and the name of the shared memory
+/// segment, and can be turned into a Mumble `ErrorCode` with `code()`.
+#[derive(Debug)]
+pub struct Error {
+    code: ErrorCode,
+    kind: ErrorKind,
+    segment: String,
+    source: io::Error,
+}
+
+impl Error {
+    /// An error opening `segment`, for implementors of `Backend`.
+    pub fn new(code: ErrorCode, segment: &str, source: io::Error) -> Error {
+        Error {
+            code,
+            kind: ErrorKind::System,
+            segment: segment.to_owned(),
+            source,
+        }
+    }
+
+    /// `segment` failed the ownership checks, for the reason in `source`.
+    pub(crate) fn insecure(segment: &str, source: io::Error) -> Error {
+        Error {
+            kind: ErrorKind::Insecure,
+            ..Error::new(ErrorCode::ShmOpen, segment, source)
+        }
+    }
+
+    /// `segment` is too small to hold the link, as described by `source`.
+    pub(crate) fn too_small(segment: &str, source: io::Error) -> Error {
+        Error {
+            kind: ErrorKind::TooSmall,
+            ..Error::new(ErrorCode::MMap, segment, source)
+        }
+    }
+
+    /// Capture `errno` (or `GetLastError()`) for a call which just failed.
+    pub(crate) fn last_os_error(code: ErrorCode, segment: &str) -> Error {
+        Error::new(code, segment, io::Error::last_os_error())
+    }
+
+    /// The error for a link which was closed with `deactivate()`.
+    pub(crate) fn deactivated() -> Error {
+        Error::new(ErrorCode::Unknown, "", io::Error::other("manually closed"))
+    }
+
+    /// The Mumble error code for this error.
+    pub fn code(&self) -> ErrorCode {
+        self.code
+    }
+
+    /// What went wrong, in more detail than `code()`.
+    pub fn kind(&self) -> ErrorKind {
+        self.kind
+    }
+
+    /// The name of the shared memory segment which couldn't be opened. Empty
+    /// if the link was closed with `deactivate()`.
+    pub fn segment(&self) -> &str {
+        &self.segment
+    }
+
+    /// The underlying operating system error.
+    pub fn os_error(&self) -> &io::Error {
+        &self.source
+    }
+
+    /// A human-readable explanation of what probably went wrong.
+    pub fn diagnosis(&self) -> &'static str {
+        if self.segment.is_empty() {
+            return "the link was deactivated";
+        }
+        match self.kind {
+            ErrorKind::Insecure => {
+                return "another user may have created it to spy on the link; \
+                        the checks can be turned off if this is expected"
+            }
+            ErrorKind::TooSmall => return "it was not created by Mumble, or not completely",
+            ErrorKind::System => {}
+        }
+        match self.source.kind() {
+            io::ErrorKind::NotFound => "Mumble is not running, or its Link plugin is disabled",
+            io::ErrorKind::PermissionDenied => {
+                "permission denied; the shared memory belongs to another user or is not writable"
+            }
+            io::ErrorKind::AlreadyExists => {
+                "Mumble, or another host, already created it; close that first"
+            }
+            io::ErrorKind::OutOfMemory => "the system is out of memory",
+            _ => "unexpected error opening the shared memory",
+        }
+    }
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.segment.is_empty() {
+            return f.write_str(self.diagnosis());
+        }
+        write!(
+            f,
+            "{} ({}): {}: {}",
+            self.code,
+            self.segment,
+            self.source,
+            self.diagnosis()
+        )
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        Some(&self.source)
+    }
+}
+
+impl From<Error> for ErrorCode {
+    fn from(err: Error) -> ErrorCode {
+        err.code
+    }
+}
+
+#[test]
+fn test_error() {
+    let err = Error::new(
+        ErrorCode::ShmOpen,
+        "/MumbleLink.1000",
+        io::Error::from(io::ErrorKind::NotFound),
+    );
+    assert_eq!(ErrorCode::ShmOpen, err.code());
+    assert_eq!(3, err.code() as i32);
+    assert_eq!(
+        "Mumble is not running, or its Link plugin is disabled",
+        err.diagnosis()
+    );
+    assert!(err
+        .to_string()
+        .starts_with("shm_open returned a negative integer (/MumbleLink.1000): "));
+
+    let err = Error::new(
+        ErrorCode::ShmOpen,
+        "/MumbleLink.1000",
+        io::Error::from_raw_os_error(::libc::EACCES),
+    );
+    assert!(err.diagnosis().starts_with("permission denied"));
+
+    // Every code can be displayed on every platform
+    for code in &[
+        ErrorCode::OpenFileMappingW,
+        ErrorCode::ShmOpen,
+        ErrorCode::Unknown,
+    ] {
+        assert!(!code.to_string().is_empty());
+    }
+}
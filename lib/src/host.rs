@@ -0,0 +1,172 @@
+use libc::wchar_t;
+use std::time::{Duration, Instant};
+use std::{fmt, mem};
+
+use super::{Backend, Error, Frame, LinkedMem, ProbingBackend, Segment, LINK_SIZE};
+
+/// How long Mumble keeps using the link after the tick stops changing.
+pub const LINK_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The Mumble side of the link, for testing without a running Mumble.
+///
+/// Creating a host creates the shared memory segment the way Mumble does, and
+/// `poll()` decides whether an application is linked the way Mumble's Link
+/// plugin does. The segment is removed again when the host is dropped.
+/// Creating a host fails while a real Mumble is running, since it would take
+/// over Mumble's segment.
+pub struct LinkHost {
+    segment: Box<dyn Segment>,
+    timeout: Duration,
+    linked: bool,
+    last_tick: u32,
+    ticked_at: Instant,
+}
+
+impl LinkHost {
+    /// Create the Mumble link segment where Mumble would, honoring the same
+    /// environment variables as `ProbingBackend::from_env()`.
+    pub fn new() -> Result<LinkHost, Error> {
+        LinkHost::with_backend(&ProbingBackend::from_env())
+    }
+
+    /// Create the link segment in `backend`.
+    pub fn with_backend(backend: &dyn Backend) -> Result<LinkHost, Error> {
+        Ok(LinkHost {
+            segment: backend.create(LINK_SIZE)?,
+            timeout: LINK_TIMEOUT,
+            linked: false,
+            last_tick: 0,
+            ticked_at: Instant::now(),
+        })
+    }
+
+    /// Change how long an application stays linked after its tick stops
+    /// changing. Defaults to `LINK_TIMEOUT`, like Mumble.
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = timeout;
+    }
+
+    /// Whether an application was linked as of the last `poll()`.
+    pub fn is_linked(&self) -> bool {
+        self.linked
+    }
+
+    /// Read the segment as it is, without any of the plugin's checks.
+    pub fn read(&self) -> Frame {
+        Frame::decode(&LinkedMem::read(&*self.segment))
+    }
+
+    /// Fetch the positions like Mumble does every audio frame, returning the
+    /// frame if an application is linked.
+    ///
+    /// An application links once it writes a known version (1 or 2) with a
+    /// tick different from the last one seen. It stays linked as long as the
+    /// version stays valid and the tick keeps changing at least once per
+    /// timeout. Once it times out, the version and the first character of
+    /// the name are zeroed, so the application must write them again.
+    pub fn poll(&mut self) -> Option<Frame> {
+        let frame = self.read();
+        let now = Instant::now();
+        if frame.tick != self.last_tick {
+            self.last_tick = frame.tick;
+            self.ticked_at = now;
+            self.linked = true;
+        } else if now.duration_since(self.ticked_at) > self.timeout {
+            if self.linked {
+                self.unlock();
+            }
+            self.linked = false;
+        }
+        if frame.version != 1 && frame.version != 2 {
+            self.linked = false;
+        }
+
+        if self.linked {
+            Some(frame)
+        } else {
+            None
+        }
+    }
+
+    /// Forget about the application, like the plugin's `unlock()`.
+    fn unlock(&mut self) {
+        self.segment
+            .write(mem::offset_of!(LinkedMem, ui_version), &[0; 4]);
+        self.segment.write(
+            mem::offset_of!(LinkedMem, name),
+            &[0; mem::size_of::<wchar_t>()],
+        );
+    }
+}
+
+impl fmt::Debug for LinkHost {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("LinkHost")
+            .field("linked", &self.linked)
+            .field("last_tick", &self.last_tick)
+            .finish()
+    }
+}
+
+#[test]
+fn test_host() {
+    use super::{imp, MumbleLink, Position, ShmBackend};
+
+    // The real thing, under another name
+    let backend = ShmBackend::named(&imp::test_name("host"));
+    let mut host = LinkHost::with_backend(&backend).unwrap();
+    host.set_timeout(Duration::from_millis(50));
+    assert!(host.poll().is_none());
+
+    let mut link = MumbleLink::with_backend(Box::new(backend), "Game", "A game.").unwrap();
+    assert!(host.poll().is_none());
+    link.update(Position::default(), Position::default());
+    let frame = host.poll().unwrap();
+    assert_eq!("Game", frame.name);
+    assert_eq!(1, frame.tick);
+
+    // Mumble unlinks once the tick stops changing, clearing the version and
+    // name...
+    ::std::thread::sleep(Duration::from_millis(100));
+    assert!(host.poll().is_none());
+    let frame = host.read();
+    assert_eq!(0, frame.version);
+    assert_eq!("", frame.name);
+    // ...and links again once it moves.
+    link.update(Position::default(), Position::default());
+    assert_eq!("Game", host.poll().unwrap().name);
+
+    // Dropping the link zeroes the segment
+    drop(link);
+    assert!(host.poll().is_none());
+    assert!(!host.read().is_active());
+}
+
+#[test]
+fn test_host_shared() {
+    use super::{MemoryBackend, Position, SharedLink, Status, RETRY_INTERVAL};
+
+    let backend = MemoryBackend::new();
+    let mut host = LinkHost::with_backend(&backend).unwrap();
+
+    let mut first = SharedLink::with_backend(Box::new(backend.clone()), "First", "");
+    first.update(Position::default(), Position::default());
+    assert!(matches!(first.status(), Status::Active));
+    assert_eq!("First", host.poll().unwrap().name);
+
+    // A second link leaves the first one alone
+    let mut second = SharedLink::with_backend(Box::new(backend.clone()), "Second", "");
+    second.update(Position::default(), Position::default());
+    match second.status() {
+        Status::InUse { name, .. } => assert_eq!("First", name),
+        status => panic!("unexpected status {:?}", status),
+    }
+    assert_eq!("First", host.poll().unwrap().name);
+
+    // Once the first one is gone, the second takes over on its next retry
+    drop(first);
+    second.checked_at -= RETRY_INTERVAL;
+    second.update(Position::default(), Position::default());
+    assert!(matches!(second.status(), Status::Active));
+    assert_eq!("Second", host.poll().unwrap().name);
+}
@@ -5,65 +5,102 @@
 //!
 //! Connect to Mumble link with `MumbleLink::new()`, set the context or player
 //! identity as needed, and call `update()` every frame with the position data.
+//! To see what is currently in the link, use `LinkReader`.
 
 extern crate libc;
+#[macro_use]
+extern crate log;
 extern crate winapi;
 
 use libc::{c_float, wchar_t};
-use std::{fmt::Display, io, mem, ptr};
+use std::sync::mpsc::{self, Receiver};
+use std::time::{Duration, Instant};
+use std::{fmt::Display, mem};
 
-#[cfg_attr(not(windows), allow(unused_macros))]
+#[cfg_attr(not(test), allow(unused_macros))]
 macro_rules! wide {
     ($($ch:ident)*) => {
         [$(stringify!($ch).as_bytes()[0] as ::libc::wchar_t,)* 0]
     }
 }
 
-/// The error to send to mumble.
-/// See: https://www.mumble.info/documentation/developer/positional-audio/link-plugin/
-#[repr(i32)]
-#[derive(Debug, Clone, Copy)]
-#[non_exhaustive]
-pub enum ErrorCode {
-    Success = 0,
-    OpenFileMappingW = 1,
-    MapViewOfFile = 2,
-    ShmOpen = 3,
-    MMap = 4,
-    NoMem = 5,
-    Unknown = 6,
+mod backend;
+mod driver;
+mod error;
+mod host;
+mod reader;
+mod retry;
+mod sync;
+mod watchdog;
+pub use backend::{
+    Backend, FileBackend, MemoryBackend, Overrides, ProbingBackend, Segment, SegmentId, ShmBackend,
+};
+pub use driver::LinkDriver;
+pub use error::{Error, ErrorCode, ErrorKind};
+pub use host::{LinkHost, LINK_TIMEOUT};
+pub use reader::{Frame, LinkReader};
+pub use retry::RetryPolicy;
+pub use sync::SyncLink;
+use watchdog::Watchdog;
+
+/// The maximum length of the context, in bytes.
+pub const MAX_CONTEXT_LEN: usize = 256;
+
+/// The maximum length of the identity, in UTF-16 code units on Windows and
+/// UTF-32 code units elsewhere.
+pub const MAX_IDENTITY_LEN: usize = 255;
+
+/// A context or identity was too long for the link, and only the first `max`
+/// bytes or code units of it were stored.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Truncated {
+    /// The length of the value that was passed in.
+    pub len: usize,
+    /// The length of the value that was stored.
+    pub max: usize,
 }
 
-impl Display for ErrorCode {
+impl Display for Truncated {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        f.write_str(if let Self::Success = self {
-            "no error"
-        } else if let Self::NoMem = self {
-            "shared memory was not initialized"
-        } else if let Self::Unknown = self {
-            "unknown Error"
-        } else if cfg!(windows) {
-            match self {
-                ErrorCode::OpenFileMappingW => "OpenFileMappingW failed to return a handle",
-                ErrorCode::MapViewOfFile => "MapViewOfFile failed to return a structure",
-                _ => unreachable!(),
-            }
-        } else if cfg!(unix) {
-            match self {
-                ErrorCode::ShmOpen => "shm_open returned a negative integer",
-                ErrorCode::MMap => "mmap failed to return a structure",
-                _ => unreachable!(),
-            }
-        } else {
-            unreachable!()
-        })
+        write!(
+            f,
+            "value of length {} was truncated to {}",
+            self.len, self.max
+        )
     }
 }
 
+impl std::error::Error for Truncated {}
+
 #[cfg_attr(windows, path = "windows.rs")]
 #[cfg_attr(not(windows), path = "unix.rs")]
 mod imp;
 
+/// The size of the link segment.
+const LINK_SIZE: usize = mem::size_of::<LinkedMem>();
+
+/// Where each group of fields in `LinkedMem` ends. A segment smaller than
+/// `LINK_SIZE` only gets the groups which fit in it completely, so Mumble
+/// never sees half a name or a context length without its context.
+const FIELD_ENDS: [usize; 6] = [
+    mem::offset_of!(LinkedMem, name),
+    mem::offset_of!(LinkedMem, camera),
+    mem::offset_of!(LinkedMem, identity),
+    mem::offset_of!(LinkedMem, context_len),
+    mem::offset_of!(LinkedMem, description),
+    LINK_SIZE,
+];
+
+/// The smallest segment worth linking to: the version, tick and avatar.
+const MIN_LINK_SIZE: usize = FIELD_ENDS[0];
+
+/// How often an open link checks whether Mumble has replaced its segment.
+const STALE_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How often a `SharedLink` which is not active tries to (re)open the link,
+/// unless its `RetryPolicy` says otherwise.
+const RETRY_INTERVAL: Duration = Duration::from_secs(1);
+
 /// A position in three-dimensional space.
 ///
 /// The vectors are in a left-handed coordinate system: X positive towards
@@ -71,6 +108,7 @@ mod imp;
 /// is treated as one meter by the sound engine.
 ///
 /// `front` and `top` should be unit vectors and perpendicular to each other.
+#[repr(C)]
 #[derive(Copy, Clone, Debug)]
 pub struct Position {
     /// The character's position in space.
@@ -94,6 +132,9 @@ impl Default for Position {
     }
 }
 
+/// The shared memory segment, laid out as the `LinkedMem` struct Mumble's
+/// Link plugin reads.
+#[repr(C)]
 #[derive(Copy, Debug)]
 struct LinkedMem {
     #[cfg(windows)]
@@ -116,6 +157,32 @@ struct LinkedMem {
     description: [wchar_t; 2048],
 }
 
+// Mumble reads the segment as a C struct, so its layout must never change.
+// Every field is 4-byte aligned, so there is no padding.
+#[cfg(all(
+    target_os = "linux",
+    any(target_arch = "x86_64", target_arch = "x86", target_arch = "aarch64")
+))]
+const _: () = {
+    use std::mem::{align_of, offset_of, size_of};
+    assert!(size_of::<Position>() == 36);
+    assert!(offset_of!(Position, position) == 0);
+    assert!(offset_of!(Position, front) == 12);
+    assert!(offset_of!(Position, top) == 24);
+
+    assert!(size_of::<LinkedMem>() == 10580);
+    assert!(align_of::<LinkedMem>() == 4);
+    assert!(offset_of!(LinkedMem, ui_version) == 0);
+    assert!(offset_of!(LinkedMem, ui_tick) == 4);
+    assert!(offset_of!(LinkedMem, avatar) == 8);
+    assert!(offset_of!(LinkedMem, name) == 44);
+    assert!(offset_of!(LinkedMem, camera) == 1068);
+    assert!(offset_of!(LinkedMem, identity) == 1104);
+    assert!(offset_of!(LinkedMem, context_len) == 2128);
+    assert!(offset_of!(LinkedMem, context) == 2132);
+    assert!(offset_of!(LinkedMem, description) == 2388);
+};
+
 impl Clone for LinkedMem {
     fn clone(&self) -> Self {
         *self
@@ -140,15 +207,17 @@ impl LinkedMem {
         result
     }
 
-    fn set_context(&mut self, context: &[u8]) {
-        let len = std::cmp::min(context.len(), self.context.len());
+    fn set_context(&mut self, context: &[u8]) -> Result<(), Truncated> {
+        let len = std::cmp::min(context.len(), MAX_CONTEXT_LEN);
         self.context[..len].copy_from_slice(&context[..len]);
         self.context_len = len as u32;
+        truncated(context.len(), MAX_CONTEXT_LEN)
     }
 
     #[inline]
-    fn set_identity(&mut self, identity: &str) {
-        imp::copy(&mut self.identity, identity);
+    fn set_identity(&mut self, identity: &str) -> Result<(), Truncated> {
+        let len = imp::copy(&mut self.identity, identity);
+        truncated(len, MAX_IDENTITY_LEN)
     }
 
     fn update(&mut self, avatar: Position, camera: Position) {
@@ -156,10 +225,174 @@ impl LinkedMem {
         self.avatar = avatar;
         self.camera = camera;
     }
+
+    /// Read the fields which fit in `segment`, leaving the rest zeroed.
+    fn read(segment: &dyn Segment) -> LinkedMem {
+        // Every bit pattern is a valid `LinkedMem`
+        let mut mem: LinkedMem = unsafe { mem::zeroed() };
+        let bytes = unsafe {
+            std::slice::from_raw_parts_mut(&mut mem as *mut LinkedMem as *mut u8, LINK_SIZE)
+        };
+        segment.read(&mut bytes[..fitting(segment)]);
+        mem
+    }
+
+    /// Read just the version and tick.
+    fn read_header(segment: &dyn Segment) -> (u32, u32) {
+        let mut header = [0; 8];
+        segment.read(&mut header);
+        let word =
+            |i: usize| u32::from_ne_bytes([header[i], header[i + 1], header[i + 2], header[i + 3]]);
+        (word(0), word(4))
+    }
+
+    fn bytes(&self) -> &[u8] {
+        unsafe { std::slice::from_raw_parts(self as *const LinkedMem as *const u8, LINK_SIZE) }
+    }
+
+    /// Whether `segment` still has our version and name. Mumble clears both
+    /// when it stops listening to an application whose tick stood still.
+    fn is_intact(&self, segment: &dyn Segment) -> bool {
+        const VERSION_END: usize = mem::offset_of!(LinkedMem, ui_tick);
+        const NAME: usize = mem::offset_of!(LinkedMem, name);
+        const NAME_END: usize = mem::offset_of!(LinkedMem, camera);
+
+        let mut buf = [0; NAME_END];
+        let has_name = fitting(segment) >= NAME_END;
+        let len = if has_name { NAME_END } else { VERSION_END };
+        segment.read(&mut buf[..len]);
+        let ours = self.bytes();
+        buf[..VERSION_END] == ours[..VERSION_END]
+            && (!has_name || buf[NAME..NAME_END] == ours[NAME..NAME_END])
+    }
+
+    /// Write the fields which fit in `segment`, the tick last, so Mumble
+    /// never sees a new tick before the positions and context that go with
+    /// it. The name, identity and description are left alone unless `full`,
+    /// as they rarely change and are most of the structure.
+    fn publish(&self, segment: &mut dyn Segment, full: bool) {
+        use std::mem::offset_of;
+
+        let bytes = self.bytes();
+        let len = fitting(segment);
+        let mut put = |start: usize, end: usize| {
+            if end <= len {
+                segment.write(start, &bytes[start..end]);
+            }
+        };
+        // Always, as Mumble zeroes it when it stops listening
+        put(
+            offset_of!(LinkedMem, ui_version),
+            offset_of!(LinkedMem, ui_tick),
+        );
+        if full {
+            put(offset_of!(LinkedMem, name), offset_of!(LinkedMem, camera));
+            put(
+                offset_of!(LinkedMem, identity),
+                offset_of!(LinkedMem, context_len),
+            );
+            put(offset_of!(LinkedMem, description), LINK_SIZE);
+        }
+        put(offset_of!(LinkedMem, avatar), offset_of!(LinkedMem, name));
+        put(
+            offset_of!(LinkedMem, camera),
+            offset_of!(LinkedMem, identity),
+        );
+        put(
+            offset_of!(LinkedMem, context_len),
+            offset_of!(LinkedMem, description),
+        );
+        segment.publish(offset_of!(LinkedMem, ui_tick), self.ui_tick);
+    }
+}
+
+/// The link data as set by this process, and whether the segment it is
+/// written to has the current name, identity and description yet.
+#[derive(Debug)]
+struct Local {
+    mem: LinkedMem,
+    synced: bool,
+}
+
+impl Local {
+    fn new(name: &str, description: &str) -> Local {
+        Local {
+            mem: LinkedMem::new(name, description),
+            synced: false,
+        }
+    }
+
+    fn set_identity(&mut self, identity: &str) -> Result<(), Truncated> {
+        let previous = self.mem.identity;
+        let result = self.mem.set_identity(identity);
+        if self.mem.identity != previous {
+            self.synced = false;
+        }
+        result
+    }
+
+    /// Write to `segment`, which must be the one written to last time unless
+    /// `desync()` was called since. If Mumble cleared the strings in the
+    /// meantime, they are written again.
+    fn publish(&mut self, segment: &mut dyn Segment) {
+        if self.synced && !self.mem.is_intact(segment) {
+            debug!("Mumble link was reset, writing everything again");
+            self.synced = false;
+        }
+        self.mem.publish(segment, !self.synced);
+        self.synced = true;
+    }
+
+    /// Write everything on the next `publish()`, such as to a new segment.
+    fn desync(&mut self) {
+        self.synced = false;
+    }
+}
+
+/// How much of `LinkedMem` fits in `segment`, in whole groups of fields.
+fn fitting(segment: &dyn Segment) -> usize {
+    let size = segment.size();
+    FIELD_ENDS
+        .iter()
+        .rev()
+        .cloned()
+        .find(|&end| end <= size)
+        .unwrap_or(0)
+}
+
+/// Open the link segment in `backend`, which must at least hold the version,
+/// tick and avatar. Smaller segments than Mumble's get the fields that fit.
+fn open_segment(backend: &dyn Backend, writable: bool) -> Result<Box<dyn Segment>, Error> {
+    let segment = backend.open(LINK_SIZE, writable)?;
+    let size = segment.size();
+    if size < MIN_LINK_SIZE {
+        let err = std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            format!("{} bytes long, expected at least {}", size, MIN_LINK_SIZE),
+        );
+        return Err(Error::too_small(&backend.name(), err));
+    }
+    if size < LINK_SIZE {
+        warn!(
+            "Mumble link at {} is only {} of {} bytes, leaving out the fields that don't fit",
+            backend.name(),
+            size,
+            LINK_SIZE
+        );
+    }
+    Ok(segment)
+}
+
+fn truncated(len: usize, max: usize) -> Result<(), Truncated> {
+    if len > max {
+        Err(Truncated { len, max })
+    } else {
+        Ok(())
+    }
 }
 
 macro_rules! docs {
-    ($(#[$attr:meta])* pub fn set_context(&mut $s:ident, $c:ident: &[u8]) $b:block) => {
+    ($(#[$attr:meta])* pub fn set_context(&mut $s:ident, $c:ident: &[u8]) -> $r:ty $b:block) => {
         /// Update the context string, used to determine which users on a Mumble
         /// server should hear each other positionally.
         ///
@@ -173,11 +406,12 @@ macro_rules! docs {
         /// The context should be changed infrequently, at most a few times per
         /// second.
         ///
-        /// The context has a maximum length of 256 bytes.
+        /// The context has a maximum length of 256 bytes. Longer contexts are
+        /// truncated, and `Err(Truncated)` is returned.
         $(#[$attr])*
-        pub fn set_context(&mut $s, $c: &[u8]) $b
+        pub fn set_context(&mut $s, $c: &[u8]) -> $r $b
     };
-    ($(#[$attr:meta])* pub fn set_identity(&mut $s:ident, $i:ident: &str) $b:block) => {
+    ($(#[$attr:meta])* pub fn set_identity(&mut $s:ident, $i:ident: &str) -> $r:ty $b:block) => {
         /// Update the identity, uniquely identifying the player in the given
         /// context. This is usually the in-game name or ID.
         ///
@@ -190,9 +424,11 @@ macro_rules! docs {
         /// The identity should be changed infrequently, at most a few times per
         /// second.
         ///
-        /// The identity has a maximum length of 255 UTF-16 code units.
+        /// The identity has a maximum length of 255 UTF-16 code units (UTF-32 on
+        /// platforms other than Windows). Longer identities are truncated, and
+        /// `Err(Truncated)` is returned.
         $(#[$attr])*
-        pub fn set_identity(&mut $s, $i: &str) $b
+        pub fn set_identity(&mut $s, $i: &str) -> $r $b
     };
     ($(#[$attr:meta])* pub fn update(&mut $s:ident, $a:ident: Position, $c:ident: Position) $b:block) => {
         /// Update the link with the latest position information. Should be called
@@ -208,13 +444,16 @@ macro_rules! docs {
 
 /// An active Mumble link connection.
 pub struct MumbleLink {
-    map: imp::Map,
-    local: LinkedMem,
+    backend: Box<dyn Backend>,
+    segment: Box<dyn Segment>,
+    local: Local,
+    checked_at: Instant,
+    watchdog: Option<Watchdog>,
 }
 
 impl std::fmt::Debug for MumbleLink {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        self.local.fmt(f)
+        self.local.mem.fmt(f)
     }
 }
 
@@ -225,44 +464,96 @@ impl MumbleLink {
     /// Opening the link will fail if Mumble is not running. If another
     /// application is also using Mumble link, its data may be overwritten or
     /// conflict with this link. To avoid this, use `SharedLink`.
-    pub fn new(name: &str, description: &str) -> Result<Self, ErrorCode> {
+    ///
+    /// If Mumble is restarted while the link is open, `update()` will notice
+    /// and reopen the new shared memory segment.
+    ///
+    /// The segment is looked for wherever Mumble may have put it; see
+    /// `ProbingBackend::from_env()`.
+    pub fn new(name: &str, description: &str) -> Result<Self, Error> {
+        MumbleLink::with_backend(Box::new(ProbingBackend::from_env()), name, description)
+    }
+
+    /// Like `new()`, but store the link data in `backend`.
+    pub fn with_backend(
+        backend: Box<dyn Backend>,
+        name: &str,
+        description: &str,
+    ) -> Result<Self, Error> {
         Ok(Self {
-            map: imp::Map::new(std::mem::size_of::<LinkedMem>())?,
-            local: LinkedMem::new(name, description),
+            segment: open_segment(&*backend, true)?,
+            backend,
+            local: Local::new(name, description),
+            checked_at: Instant::now(),
+            watchdog: None,
         })
     }
 
+    /// Where the link data is stored, such as the name of the shared memory
+    /// segment.
+    pub fn location(&self) -> String {
+        self.backend.name()
+    }
+
+    /// Zero the avatar, disabling positional audio, whenever `update()` has
+    /// not been called for `idle`, such as when the game froze or is in a
+    /// menu. The next `update()` writes it again.
+    ///
+    /// The watchdog runs on a background thread until the link is dropped.
+    /// Calling this again changes `idle`.
+    pub fn set_watchdog(&mut self, idle: Duration) {
+        match self.watchdog {
+            Some(ref watchdog) => watchdog.set_idle(idle),
+            None => {
+                let watchdog = Watchdog::spawn(idle);
+                watchdog.watch_in_place(&mut self.segment);
+                self.watchdog = Some(watchdog);
+            }
+        }
+    }
+
     docs! {
         #[inline]
-        pub fn set_context(&mut self, context: &[u8]) {
-            self.local.set_context(context)
+        pub fn set_context(&mut self, context: &[u8]) -> Result<(), Truncated> {
+            self.local.mem.set_context(context)
         }
     }
     docs! {
         #[inline]
-        pub fn set_identity(&mut self, identity: &str) {
+        pub fn set_identity(&mut self, identity: &str) -> Result<(), Truncated> {
             self.local.set_identity(identity)
         }
     }
     docs! {
         #[inline]
         pub fn update(&mut self, avatar: Position, camera: Position) {
-            self.local.update(avatar, camera);
-            unsafe {
-                ptr::write_volatile(self.map.ptr as *mut LinkedMem, self.local);
+            self.local.mem.update(avatar, camera);
+
+            // If Mumble was restarted, switch over to its new segment
+            if self.checked_at.elapsed() >= STALE_CHECK_INTERVAL {
+                self.checked_at = Instant::now();
+                if backend::is_stale(&*self.backend, &*self.segment) {
+                    debug!("Mumble link segment is stale, reopening");
+                    if let Ok(segment) = open_segment(&*self.backend, true) {
+                        info!("Mumble was restarted, reopened the link");
+                        self.segment = match self.watchdog {
+                            Some(ref watchdog) => watchdog.watch(segment),
+                            None => segment,
+                        };
+                        self.local.desync();
+                    }
+                }
             }
+
+            self.local.publish(&mut *self.segment);
         }
     }
 }
 
-unsafe impl Send for MumbleLink {}
-
 impl Drop for MumbleLink {
     fn drop(&mut self) {
-        unsafe {
-            // zero the linked memory
-            ptr::write_volatile(self.map.ptr as *mut LinkedMem, mem::zeroed());
-        }
+        // zero the linked memory
+        self.segment.write(0, &[0; LINK_SIZE]);
     }
 }
 
@@ -270,98 +561,367 @@ impl Drop for MumbleLink {
 ///
 /// Constructing a `SharedLink` always succeeds, even if Mumble is not running
 /// or another application is writing to the link. If this happens, `update()`
-/// will retry opening the link on a regular basis, succeeding if Mumble is
-/// started or the other application stops using the link.
+/// will retry opening the link about once a second, succeeding if Mumble is
+/// started or the other application stops using the link. How and how often
+/// can be changed with a `RetryPolicy`; see `SharedLink::builder()`.
+///
+/// If another application starts writing to the link while it is active, the
+/// link stops and waits for it, or keeps writing, depending on its `Priority`.
 pub struct SharedLink {
+    backend: Box<dyn Backend>,
     inner: Inner,
-    local: LinkedMem,
+    local: Local,
+    retry: RetryPolicy,
+    checked_at: Instant,
+    /// Failed attempts to open the link in a row.
+    failures: u32,
+    priority: Priority,
+    listeners: Vec<Listener>,
+    watchdog: Option<Watchdog>,
 }
 
+type Listener = Box<dyn FnMut(&StatusEvent) + Send>;
+
 impl SharedLink {
     /// Open the Mumble link, providing the specified application name and
-    /// description.
+    /// description. The segment is looked for like `MumbleLink::new()` does.
     pub fn new(name: &str, description: &str) -> SharedLink {
-        SharedLink {
-            inner: Inner::open(),
-            local: LinkedMem::new(name, description),
+        SharedLink::builder(name, description).build()
+    }
+
+    /// Like `new()`, but store the link data in `backend`.
+    pub fn with_backend(backend: Box<dyn Backend>, name: &str, description: &str) -> SharedLink {
+        SharedLink::builder(name, description)
+            .backend(backend)
+            .build()
+    }
+
+    /// Configure a link before opening it.
+    pub fn builder(name: &str, description: &str) -> SharedLinkBuilder {
+        SharedLinkBuilder {
+            name: name.to_owned(),
+            description: description.to_owned(),
+            backend: None,
+            retry: RetryPolicy::default(),
+            priority: Priority::default(),
+            watchdog: None,
         }
     }
 
     docs! {
         #[inline]
-        pub fn set_context(&mut self, context: &[u8]) {
-            self.local.set_context(context)
+        pub fn set_context(&mut self, context: &[u8]) -> Result<(), Truncated> {
+            self.local.mem.set_context(context)
         }
     }
 
     docs! {
         #[inline]
-        pub fn set_identity(&mut self, identity: &str) {
+        pub fn set_identity(&mut self, identity: &str) -> Result<(), Truncated> {
             self.local.set_identity(identity)
         }
     }
 
     docs! {
         pub fn update(&mut self, avatar: Position, camera: Position) {
-            self.local.update(avatar, camera);
+            let published = self.local.mem.ui_tick;
+            self.local.mem.update(avatar, camera);
 
-            // If it's been a hundred ticks, try to reopen the link
-            if self.local.ui_tick % 100 == 0 {
+            // If it's been a while, try to reopen the link. This is based on
+            // wall-clock time so the retry rate doesn't depend on frame rate.
+            let delay = match self.inner {
+                Inner::Closed(_) => self.retry.delay(self.failures),
+                _ => self.retry.check_interval(),
+            };
+            let gave_up =
+                matches!(self.inner, Inner::Closed(_)) && self.retry.gives_up(self.failures);
+            if !gave_up && self.checked_at.elapsed() >= delay {
+                self.checked_at = Instant::now();
+                let before = self.inner.kind();
+                let mut restarted = false;
                 self.inner = match mem::replace(&mut self.inner, Inner::Unset) {
-                    Inner::Closed(_) => Inner::open(),
-                    Inner::InUse(map, last_tick) => {
-                        let previous = unsafe { ptr::read_volatile(map.ptr as *mut LinkedMem) };
-                        if previous.ui_version == 0 || last_tick == previous.ui_tick {
-                            Inner::Active(map)
+                    Inner::Closed(_) => {
+                        self.local.desync();
+                        let inner = Inner::open(&*self.backend, self.watchdog.as_ref());
+                        if let Inner::Closed(ref e) = inner {
+                            self.failures += 1;
+                            if self.retry.gives_up(self.failures) {
+                                warn!(
+                                    "Giving up on the Mumble link after {} attempts: {}",
+                                    self.failures, e
+                                );
+                            }
                         } else {
-                            Inner::InUse(map, previous.ui_tick)
+                            self.failures = 0;
                         }
+                        inner
+                    }
+                    // Mumble was restarted, so the segment we hold is orphaned
+                    Inner::InUse(ref segment, ..)
+                    | Inner::Active(ref segment)
+                    | Inner::Contested(ref segment, ..)
+                        if backend::is_stale(&*self.backend, &**segment) =>
+                    {
+                        info!("Mumble link segment was replaced or removed, reopening");
+                        self.local.desync();
+                        // If Mumble exited rather than restarted, it just closes
+                        let inner = Inner::open(&*self.backend, self.watchdog.as_ref());
+                        restarted = !matches!(inner, Inner::Closed(_));
+                        inner
                     }
-                    Inner::Active(map) => Inner::Active(map),
+                    Inner::InUse(segment, last_tick, ticked_at) => {
+                        let previous = LinkedMem::read(&*segment);
+                        let ticked_at = if previous.ui_tick != last_tick {
+                            Instant::now()
+                        } else {
+                            ticked_at
+                        };
+                        if previous.ui_version == 0 || ticked_at.elapsed() >= self.retry.grace() {
+                            info!("Mumble link is no longer in use, taking over");
+                            self.local.desync();
+                            Inner::Active(segment)
+                        } else {
+                            Inner::InUse(segment, previous.ui_tick, ticked_at)
+                        }
+                    }
+                    inner @ Inner::Active(_) | inner @ Inner::Contested(..) => inner,
                     Inner::Unset => unreachable!(),
                 };
+                if restarted {
+                    self.notify(StatusEvent::Restarted);
+                }
+                self.notify_change(before);
             }
 
-            // If the link is active, write to it
-            if let Inner::Active(ref mut map) = self.inner {
-                unsafe {
-                    ptr::write_volatile(map.ptr as *mut LinkedMem, self.local);
+            // If the link is active, and still ours to write to, write to it
+            self.check_rival(published);
+            match self.inner {
+                Inner::Active(ref mut segment) | Inner::Contested(ref mut segment, ..) => {
+                    self.local.publish(&mut **segment)
                 }
+                _ => {}
             }
         }
     }
 
+    /// Where the link data is stored, such as the name of the shared memory
+    /// segment. Once the link is open, this is where it was found.
+    pub fn location(&self) -> String {
+        self.backend.name()
+    }
+
     /// Get the status of the shared link. See `Status` for details.
-    pub fn status(&self) -> Status {
+    pub fn status(&self) -> Status<'_> {
         match self.inner {
             Inner::Closed(ref err) => Status::Closed(err),
-            Inner::InUse(ref map, _) => {
-                let previous = unsafe { ptr::read_volatile(map.ptr as *mut LinkedMem) };
+            Inner::InUse(ref segment, ..) => {
+                let previous = LinkedMem::read(&**segment);
                 Status::InUse {
                     name: imp::read(&previous.name),
                     description: imp::read(&previous.description),
                 }
             }
             Inner::Active(_) => Status::Active,
+            Inner::Contested(_, ref rival, _) => Status::Contested {
+                name: rival.name.clone(),
+                description: rival.description.clone(),
+            },
             Inner::Unset => unreachable!(),
         }
     }
 
+    /// Call `listener` whenever the status changes, such as when the link
+    /// opens or another application starts using it, and when Mumble is
+    /// restarted. Listeners are called from `update()` and `deactivate()`,
+    /// after the change.
+    pub fn on_status<F>(&mut self, listener: F)
+    where
+        F: FnMut(&StatusEvent) + Send + 'static,
+    {
+        self.listeners.push(Box::new(listener));
+    }
+
+    /// Receive status changes through a channel, like `on_status()`. The
+    /// sender is dropped along with the link.
+    pub fn subscribe(&mut self) -> Receiver<StatusEvent> {
+        let (sender, receiver) = mpsc::channel();
+        self.on_status(move |event| {
+            let _ = sender.send(event.clone());
+        });
+        receiver
+    }
+
     /// Deactivate the shared link.
     ///
     /// Should be called when `update()` will not be called again for a while,
     /// such as if the player is no longer in-game.
     pub fn deactivate(&mut self) {
-        if let Inner::Active(ref mut map) = self.inner {
-            unsafe {
-                ptr::write_volatile(map.ptr as *mut LinkedMem, mem::zeroed());
+        match self.inner {
+            Inner::Active(ref mut segment) | Inner::Contested(ref mut segment, ..) => {
+                segment.write(0, &[0; LINK_SIZE])
             }
+            _ => {}
+        }
+        let before = self.inner.kind();
+        self.inner = Inner::Closed(Error::deactivated());
+        self.failures = 0;
+        self.notify_change(before);
+    }
+
+    /// Look for another application writing to the link since `published`
+    /// was, and yield to it or keep writing depending on the priority.
+    fn check_rival(&mut self, published: u32) {
+        // Only a segment this link has written to can tell
+        let (version, tick) = match self.inner {
+            Inner::Active(ref segment) | Inner::Contested(ref segment, ..) if self.local.synced => {
+                LinkedMem::read_header(&**segment)
+            }
+            _ => return,
+        };
+        let before = self.inner.kind();
+        if tick == published {
+            if let Inner::Contested(_, ref rival, since) = self.inner {
+                if since.elapsed() >= self.retry.grace() {
+                    info!("{} stopped writing to the Mumble link", rival.name);
+                    self.inner = match mem::replace(&mut self.inner, Inner::Unset) {
+                        Inner::Contested(segment, ..) => Inner::Active(segment),
+                        _ => unreachable!(),
+                    };
+                }
+            }
+        } else {
+            // Mumble sees the other application's strings now, so they need
+            // writing again
+            self.local.desync();
+            if version == 0 {
+                // Zeroed by an application closing the link; not a rival
+                return;
+            }
+            let segment = match mem::replace(&mut self.inner, Inner::Unset) {
+                Inner::Active(segment) | Inner::Contested(segment, ..) => segment,
+                _ => unreachable!(),
+            };
+            let other = LinkedMem::read(&*segment);
+            let rival = Rival {
+                name: imp::read(&other.name),
+                description: imp::read(&other.description),
+            };
+            self.inner = match self.priority {
+                Priority::Low => {
+                    info!(
+                        "{} is also writing to the Mumble link, yielding",
+                        rival.name
+                    );
+                    Inner::InUse(segment, tick, Instant::now())
+                }
+                Priority::High => {
+                    if before != Kind::Contested {
+                        warn!("{} is also writing to the Mumble link", rival.name);
+                    }
+                    Inner::Contested(segment, rival, Instant::now())
+                }
+            };
+        }
+        self.notify_change(before);
+    }
+
+    /// Tell the listeners if the status is no longer `before`.
+    fn notify_change(&mut self, before: Kind) {
+        if self.inner.kind() == before {
+            return;
+        }
+        let event = match self.status() {
+            Status::Closed(e) => StatusEvent::Closed {
+                code: e.code(),
+                reason: e.to_string(),
+            },
+            Status::InUse { name, description } => StatusEvent::InUse { name, description },
+            Status::Active => StatusEvent::Active {
+                taken_over: before == Kind::InUse,
+            },
+            Status::Contested { name, description } => StatusEvent::Contested { name, description },
+        };
+        self.notify(event);
+    }
+
+    fn notify(&mut self, event: StatusEvent) {
+        for listener in &mut self.listeners {
+            listener(&event);
+        }
+    }
+}
+
+/// Configures a `SharedLink`; see `SharedLink::builder()`.
+pub struct SharedLinkBuilder {
+    name: String,
+    description: String,
+    backend: Option<Box<dyn Backend>>,
+    retry: RetryPolicy,
+    priority: Priority,
+    watchdog: Option<Duration>,
+}
+
+impl SharedLinkBuilder {
+    /// Store the link data in `backend` instead of where Mumble may have put
+    /// it.
+    pub fn backend(mut self, backend: Box<dyn Backend>) -> SharedLinkBuilder {
+        self.backend = Some(backend);
+        self
+    }
+
+    /// Retry opening the link according to `retry`.
+    pub fn retry(mut self, retry: RetryPolicy) -> SharedLinkBuilder {
+        self.retry = retry;
+        self
+    }
+
+    /// What to do when another application writes to the link while this one
+    /// is using it. Defaults to `Priority::Low`.
+    pub fn priority(mut self, priority: Priority) -> SharedLinkBuilder {
+        self.priority = priority;
+        self
+    }
+
+    /// Zero the avatar when `update()` has not been called for `idle`, like
+    /// `MumbleLink::set_watchdog()` does. Off by default.
+    pub fn watchdog(mut self, idle: Duration) -> SharedLinkBuilder {
+        self.watchdog = Some(idle);
+        self
+    }
+
+    /// Open the link. Like `SharedLink::new()`, this always succeeds.
+    pub fn build(self) -> SharedLink {
+        let backend = self
+            .backend
+            .unwrap_or_else(|| Box::new(ProbingBackend::from_env()));
+        let watchdog = self.watchdog.map(Watchdog::spawn);
+        let inner = Inner::open(&*backend, watchdog.as_ref());
+        SharedLink {
+            inner,
+            backend,
+            local: Local::new(&self.name, &self.description),
+            retry: self.retry,
+            checked_at: Instant::now(),
+            failures: 0,
+            priority: self.priority,
+            listeners: Vec::new(),
+            watchdog,
         }
-        self.inner = Inner::Closed(io::Error::new(io::ErrorKind::Other, "Manually closed"));
     }
 }
 
-unsafe impl Send for SharedLink {}
+impl std::fmt::Debug for SharedLinkBuilder {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SharedLinkBuilder")
+            .field("name", &self.name)
+            .field("description", &self.description)
+            .field("backend", &self.backend.as_ref().map(|b| b.name()))
+            .field("retry", &self.retry)
+            .field("priority", &self.priority)
+            .field("watchdog", &self.watchdog)
+            .finish()
+    }
+}
 
 impl Drop for SharedLink {
     fn drop(&mut self) {
@@ -371,21 +931,63 @@ impl Drop for SharedLink {
 
 enum Inner {
     Unset,
-    Closed(io::Error),
-    InUse(imp::Map, u32),
-    Active(imp::Map),
+    Closed(Error),
+    /// The segment, the other application's last tick and when it changed.
+    InUse(Box<dyn Segment>, u32, Instant),
+    Active(Box<dyn Segment>),
+    /// Active, but another application is writing too, last seen at the time.
+    Contested(Box<dyn Segment>, Rival, Instant),
+}
+
+/// Another application writing to the link.
+struct Rival {
+    name: String,
+    description: String,
+}
+
+/// Which `Status` an `Inner` is, to tell when it changes.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    Closed,
+    InUse,
+    Active,
+    Contested,
 }
 
 impl Inner {
-    fn open() -> Inner {
-        match imp::Map::new(std::mem::size_of::<LinkedMem>()) {
-            Err(_) => Inner::Closed(io::Error::last_os_error()),
-            Ok(map) => {
-                let previous = unsafe { ptr::read_volatile(map.ptr as *mut LinkedMem) };
+    fn kind(&self) -> Kind {
+        match *self {
+            Inner::Closed(_) => Kind::Closed,
+            Inner::InUse(..) => Kind::InUse,
+            Inner::Active(_) => Kind::Active,
+            Inner::Contested(..) => Kind::Contested,
+            Inner::Unset => unreachable!(),
+        }
+    }
+
+    /// Open the segment, watched by `watchdog` if there is one.
+    fn open(backend: &dyn Backend, watchdog: Option<&Watchdog>) -> Inner {
+        let opened = open_segment(backend, true).map(|segment| match watchdog {
+            Some(watchdog) => watchdog.watch(segment),
+            None => segment,
+        });
+        match opened {
+            Err(e) => {
+                debug!("Unable to open Mumble link: {}", e);
+                Inner::Closed(e)
+            }
+            Ok(segment) => {
+                let previous = LinkedMem::read(&*segment);
                 if previous.ui_version != 0 {
-                    Inner::InUse(map, previous.ui_tick)
+                    info!(
+                        "Mumble link at {} is in use by {}",
+                        backend.name(),
+                        imp::read(&previous.name)
+                    );
+                    Inner::InUse(segment, previous.ui_tick, Instant::now())
                 } else {
-                    Inner::Active(map)
+                    info!("Mumble link opened at {}", backend.name());
+                    Inner::Active(segment)
                 }
             }
         }
@@ -397,7 +999,7 @@ impl Inner {
 pub enum Status<'a> {
     /// The link is closed. This is usually because Mumble is not running or
     /// the link was closed manually with `deactivate()`.
-    Closed(&'a io::Error),
+    Closed(&'a Error),
     /// The link is in use by another application.
     InUse {
         /// The name of the other application.
@@ -407,6 +1009,66 @@ pub enum Status<'a> {
     },
     /// The link is active.
     Active,
+    /// The link is active, but another application is writing to it as well.
+    /// This link keeps writing as its priority is `Priority::High`.
+    Contested {
+        /// The name of the other application.
+        name: String,
+        /// The description of the other application.
+        description: String,
+    },
+}
+
+/// What a `SharedLink` does when another application starts writing to the
+/// link while it is active. Two applications writing at once make Mumble
+/// jump between their positions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Priority {
+    /// Stop writing and wait for the other application to stop, as if it had
+    /// been using the link first.
+    #[default]
+    Low,
+    /// Keep writing, and report the conflict as `Status::Contested` until the
+    /// other application stops.
+    High,
+}
+
+/// A change in the status of a `SharedLink`, as passed to the listeners
+/// registered with `SharedLink::on_status()`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StatusEvent {
+    /// The link became active. If `taken_over`, another application was using
+    /// it before; otherwise it was closed.
+    Active {
+        /// Whether the link was in use by another application before.
+        taken_over: bool,
+    },
+    /// Another application is using the link, so this one is waiting for it
+    /// to stop.
+    InUse {
+        /// The name of the other application.
+        name: String,
+        /// The description of the other application.
+        description: String,
+    },
+    /// The link was closed, because it was deactivated or Mumble is gone.
+    Closed {
+        /// The error code for why.
+        code: ErrorCode,
+        /// The error, as a message.
+        reason: String,
+    },
+    /// Another application started writing to the link while this one keeps
+    /// writing too. See `Priority::High`.
+    Contested {
+        /// The name of the other application.
+        name: String,
+        /// The description of the other application.
+        description: String,
+    },
+    /// Mumble was restarted, so the link reopened its new segment. If the
+    /// status is different afterwards, another event follows.
+    Restarted,
 }
 
 #[test]
@@ -429,3 +1091,383 @@ fn test_wide() {
 
     assert_eq!("BarFoo", imp::read(&wide!(B a r F o o)));
 }
+
+#[test]
+fn test_truncated() {
+    let mut mem = LinkedMem::new("Test", "test.");
+
+    assert_eq!(Ok(()), mem.set_context(&[b'x'; MAX_CONTEXT_LEN]));
+    assert_eq!(
+        Err(Truncated {
+            len: 300,
+            max: MAX_CONTEXT_LEN
+        }),
+        mem.set_context(&[b'y'; 300])
+    );
+    assert_eq!(MAX_CONTEXT_LEN as u32, mem.context_len);
+    assert_eq!(b'y', mem.context[MAX_CONTEXT_LEN - 1]);
+
+    let long = "é".repeat(MAX_IDENTITY_LEN);
+    assert_eq!(Ok(()), mem.set_identity(&long));
+    assert_eq!(long, imp::read(&mem.identity));
+    let longer = format!("{}é", long);
+    assert_eq!(
+        Err(Truncated {
+            len: MAX_IDENTITY_LEN + 1,
+            max: MAX_IDENTITY_LEN
+        }),
+        mem.set_identity(&longer)
+    );
+    assert_eq!(long, imp::read(&mem.identity));
+}
+
+/// Build a frame byte by byte from the layout of Mumble's `LinkedMem` on
+/// Linux, and compare it against the struct's memory.
+#[cfg(all(
+    test,
+    target_os = "linux",
+    any(target_arch = "x86_64", target_arch = "x86", target_arch = "aarch64")
+))]
+#[test]
+fn test_layout() {
+    let mut mem = LinkedMem::new("Game", "A game.");
+    mem.set_context(b"server").unwrap();
+    mem.set_identity("Steve").unwrap();
+    let avatar = Position {
+        position: [1., 2., 3.],
+        front: [0., 0., 1.],
+        top: [0., 1., 0.],
+    };
+    let camera = Position {
+        position: [4., 5., 6.],
+        front: [1., 0., 0.],
+        top: [0., -1., 0.],
+    };
+    mem.update(avatar, camera);
+
+    let mut expected = vec![0u8; 10580];
+    {
+        let mut put = |offset: usize, bytes: &[u8]| {
+            expected[offset..offset + bytes.len()].copy_from_slice(bytes);
+        };
+        let floats = |v: [f32; 9]| v.iter().flat_map(|f| f.to_ne_bytes()).collect::<Vec<u8>>();
+        let wide = |s: &str| {
+            s.chars()
+                .flat_map(|c| (c as u32).to_ne_bytes())
+                .collect::<Vec<u8>>()
+        };
+
+        put(0, &2u32.to_ne_bytes());
+        put(4, &1u32.to_ne_bytes());
+        put(8, &floats([1., 2., 3., 0., 0., 1., 0., 1., 0.]));
+        put(44, &wide("Game"));
+        put(1068, &floats([4., 5., 6., 1., 0., 0., 0., -1., 0.]));
+        put(1104, &wide("Steve"));
+        put(2128, &6u32.to_ne_bytes());
+        put(2132, b"server");
+        put(2388, &wide("A game."));
+    }
+
+    let actual = unsafe {
+        std::slice::from_raw_parts(
+            &mem as *const LinkedMem as *const u8,
+            mem::size_of::<LinkedMem>(),
+        )
+    };
+    assert_eq!(&expected[..], actual);
+}
+
+#[test]
+fn test_partial() {
+    let backend = MemoryBackend::new();
+    backend.create(16).unwrap();
+    let err = MumbleLink::with_backend(Box::new(backend.clone()), "Game", "").unwrap_err();
+    assert_eq!(ErrorKind::TooSmall, err.kind());
+    assert_eq!(ErrorCode::MMap, err.code());
+
+    // Room for everything up to the middle of the identity
+    let size = FIELD_ENDS[2] + 8;
+    backend.create(size).unwrap();
+    let mut link = MumbleLink::with_backend(Box::new(backend.clone()), "Game", "").unwrap();
+    link.set_identity("Steve").unwrap();
+    link.update(Position::default(), Position::default());
+
+    let reader = LinkReader::with_backend(Box::new(backend)).unwrap();
+    let frame = reader.read();
+    assert_eq!(1, frame.tick);
+    assert_eq!("Game", frame.name);
+    assert_eq!([0., 1., 0.], frame.camera.top);
+    assert_eq!("", frame.identity);
+}
+
+#[test]
+fn test_publish() {
+    use std::sync::atomic::{fence, Ordering};
+    use std::thread;
+
+    const FRAMES: u32 = 100_000;
+
+    // Real shared memory, so the reader can catch the writer halfway through
+    let backend = ShmBackend::named(&imp::test_name("publish"));
+    let _host = LinkHost::with_backend(&backend).unwrap();
+    let reader = backend.open(LINK_SIZE, false).unwrap();
+
+    let mut link = MumbleLink::with_backend(Box::new(backend), "Game", "A game.").unwrap();
+    let writer = thread::spawn(move || {
+        for i in 1..=FRAMES {
+            let position = Position {
+                position: [i as f32; 3],
+                ..Position::default()
+            };
+            link.set_context(format!("{:08}", i).as_bytes()).unwrap();
+            link.update(position, position);
+        }
+        link
+    });
+
+    let mut tick = 0;
+    while tick < FRAMES {
+        // Like Mumble: check the tick, then look at the rest
+        let mut header = [0; 8];
+        reader.read(&mut header);
+        tick = u32::from_ne_bytes([header[4], header[5], header[6], header[7]]);
+        fence(Ordering::Acquire);
+        let mem = LinkedMem::read(&*reader);
+        if tick == 0 {
+            continue;
+        }
+
+        // Whatever was published with the tick is there, never older
+        for &value in mem.avatar.position.iter().chain(&mem.camera.position) {
+            assert!(value as u32 >= tick, "position {} at tick {}", value, tick);
+        }
+        let context = std::str::from_utf8(&mem.context[..8]).unwrap();
+        assert!(context.parse::<u32>().unwrap() >= tick);
+        assert_eq!("Game", imp::read(&mem.name));
+        assert_eq!("A game.", imp::read(&mem.description));
+    }
+    drop(writer.join().unwrap());
+}
+
+#[test]
+fn test_unchanged() {
+    let backend = MemoryBackend::new();
+    let mut segment = backend.create(LINK_SIZE).unwrap();
+    let mut link = SharedLink::with_backend(Box::new(backend), "Game", "A game.");
+    link.set_identity("Steve").unwrap();
+    link.update(Position::default(), Position::default());
+
+    // Scribble over the description; the link doesn't write it again...
+    segment.write(mem::offset_of!(LinkedMem, description), &[0; 4]);
+    link.set_identity("Steve").unwrap();
+    link.update(Position::default(), Position::default());
+    let mem = LinkedMem::read(&*segment);
+    assert_eq!("", imp::read(&mem.description));
+    assert_eq!(2, mem.ui_tick);
+
+    // ...until the identity changes
+    link.set_identity("Alex").unwrap();
+    link.update(Position::default(), Position::default());
+    let mem = LinkedMem::read(&*segment);
+    assert_eq!("A game.", imp::read(&mem.description));
+    assert_eq!("Alex", imp::read(&mem.identity));
+
+    // Or Mumble clears the version and name, as it does once it stops
+    // listening, in which case the link starts over
+    segment.write(mem::offset_of!(LinkedMem, ui_version), &[0; 4]);
+    segment.write(mem::offset_of!(LinkedMem, name), &[0; 4]);
+    segment.write(mem::offset_of!(LinkedMem, description), &[0; 4]);
+    link.update(Position::default(), Position::default());
+    let mem = LinkedMem::read(&*segment);
+    assert_eq!(2, mem.ui_version);
+    assert_eq!("Game", imp::read(&mem.name));
+    assert_eq!("A game.", imp::read(&mem.description));
+    assert!(matches!(link.status(), Status::Active));
+}
+
+#[test]
+fn test_retry() {
+    let backend = MemoryBackend::new();
+    let retry = RetryPolicy::new()
+        .interval(Duration::from_millis(0))
+        .max_attempts(2)
+        .takeover_grace(Duration::from_millis(50));
+    let mut link = SharedLink::builder("Game", "")
+        .backend(Box::new(backend.clone()))
+        .retry(retry)
+        .build();
+
+    // Two more tries, then it gives up even once Mumble is there
+    link.update(Position::default(), Position::default());
+    link.update(Position::default(), Position::default());
+    backend.create(LINK_SIZE).unwrap();
+    link.update(Position::default(), Position::default());
+    assert!(matches!(link.status(), Status::Closed(_)));
+    // Deactivating starts over
+    link.deactivate();
+    link.update(Position::default(), Position::default());
+    assert!(matches!(link.status(), Status::Active));
+
+    // Another link waits for the first to stand still for the grace period
+    let mut other = SharedLink::builder("Other", "")
+        .backend(Box::new(backend))
+        .retry(retry)
+        .build();
+    other.update(Position::default(), Position::default());
+    assert!(matches!(other.status(), Status::InUse { .. }));
+    link.update(Position::default(), Position::default());
+    other.update(Position::default(), Position::default());
+    assert!(matches!(other.status(), Status::InUse { .. }));
+    std::thread::sleep(Duration::from_millis(60));
+    other.update(Position::default(), Position::default());
+    assert!(matches!(other.status(), Status::Active));
+}
+
+#[test]
+fn test_events() {
+    let backend = MemoryBackend::new();
+    let retry = RetryPolicy::new().interval(Duration::from_millis(0));
+    let mut link = SharedLink::builder("Game", "")
+        .backend(Box::new(backend.clone()))
+        .retry(retry)
+        .build();
+    let events = link.subscribe();
+    let update = |link: &mut SharedLink| link.update(Position::default(), Position::default());
+
+    let mut segment = backend.create(LINK_SIZE).unwrap();
+    update(&mut link);
+    update(&mut link);
+    assert_eq!(
+        Ok(StatusEvent::Active { taken_over: false }),
+        events.try_recv()
+    );
+    assert!(events.try_recv().is_err());
+
+    link.deactivate();
+    match events.try_recv() {
+        Ok(StatusEvent::Closed { code, .. }) => assert_eq!(ErrorCode::Unknown, code),
+        event => panic!("unexpected event {:?}", event),
+    }
+
+    // Another application shows up, and goes away again
+    let mut other = LinkedMem::new("Other", "Another game.");
+    other.update(Position::default(), Position::default());
+    other.publish(&mut *segment, true);
+    update(&mut link);
+    let in_use = StatusEvent::InUse {
+        name: "Other".to_owned(),
+        description: "Another game.".to_owned(),
+    };
+    assert_eq!(Ok(in_use), events.try_recv());
+    segment.write(0, &[0; LINK_SIZE]);
+    update(&mut link);
+    assert_eq!(
+        Ok(StatusEvent::Active { taken_over: true }),
+        events.try_recv()
+    );
+
+    // Mumble restarts
+    backend.create(LINK_SIZE).unwrap();
+    update(&mut link);
+    assert_eq!(Ok(StatusEvent::Restarted), events.try_recv());
+    assert!(events.try_recv().is_err());
+
+    // Mumble exits
+    backend.remove();
+    update(&mut link);
+    match events.try_recv() {
+        Ok(StatusEvent::Closed { code, .. }) => assert_eq!(ErrorCode::ShmOpen, code),
+        event => panic!("unexpected event {:?}", event),
+    }
+    assert!(events.try_recv().is_err());
+}
+
+#[test]
+fn test_rival() {
+    let backend = MemoryBackend::new();
+    let mut segment = backend.create(LINK_SIZE).unwrap();
+    let mut rival = LinkedMem::new("Other", "");
+    rival.ui_tick = 1000;
+    let build = |priority| {
+        SharedLink::builder("Game", "")
+            .backend(Box::new(backend.clone()))
+            .retry(RetryPolicy::new().takeover_grace(Duration::from_millis(0)))
+            .priority(priority)
+            .build()
+    };
+    let update = |link: &mut SharedLink| link.update(Position::default(), Position::default());
+
+    // By default, the link yields to the other application
+    let mut link = build(Priority::Low);
+    update(&mut link);
+    update(&mut link);
+    rival.update(Position::default(), Position::default());
+    rival.publish(&mut *segment, true);
+    update(&mut link);
+    match link.status() {
+        Status::InUse { name, .. } => assert_eq!("Other", name),
+        status => panic!("unexpected status {:?}", status),
+    }
+    assert_eq!("Other", imp::read(&LinkedMem::read(&*segment).name));
+    link.deactivate();
+
+    // With a high priority, it keeps writing
+    segment.write(0, &[0; LINK_SIZE]);
+    let mut link = build(Priority::High);
+    let events = link.subscribe();
+    update(&mut link);
+    update(&mut link);
+    rival.update(Position::default(), Position::default());
+    rival.publish(&mut *segment, true);
+    update(&mut link);
+    assert!(matches!(link.status(), Status::Contested { .. }));
+    let contested = StatusEvent::Contested {
+        name: "Other".to_owned(),
+        description: String::new(),
+    };
+    assert_eq!(Ok(contested), events.try_recv());
+    assert_eq!("Game", imp::read(&LinkedMem::read(&*segment).name));
+
+    // Until the other application stops
+    update(&mut link);
+    assert!(matches!(link.status(), Status::Active));
+    assert_eq!(
+        Ok(StatusEvent::Active { taken_over: false }),
+        events.try_recv()
+    );
+}
+
+#[test]
+fn test_watchdog() {
+    use std::thread::sleep;
+
+    let backend = MemoryBackend::new();
+    let mut segment = backend.create(LINK_SIZE).unwrap();
+    let mut link = MumbleLink::with_backend(Box::new(backend), "Game", "").unwrap();
+    link.set_watchdog(Duration::from_secs(3600));
+    // Changes the idle time of the same watchdog, which is already waiting
+    link.set_watchdog(Duration::from_millis(20));
+    let avatar = Position {
+        position: [1., 2., 3.],
+        ..Position::default()
+    };
+    link.update(avatar, avatar);
+    assert_eq!([1., 2., 3.], LinkedMem::read(&*segment).avatar.position);
+
+    // The game stops updating
+    sleep(Duration::from_millis(100));
+    let mem = LinkedMem::read(&*segment);
+    assert_eq!([0.; 3], mem.avatar.position);
+    assert_eq!([0.; 3], mem.avatar.front);
+    assert_eq!([1., 2., 3.], mem.camera.position);
+
+    link.update(avatar, avatar);
+    assert_eq!([1., 2., 3.], LinkedMem::read(&*segment).avatar.position);
+
+    // Another application's avatar is left alone
+    let mut other = LinkedMem::new("Other", "");
+    other.update(avatar, avatar);
+    other.publish(&mut *segment, true);
+    sleep(Duration::from_millis(100));
+    assert_eq!([1., 2., 3.], LinkedMem::read(&*segment).avatar.position);
+}
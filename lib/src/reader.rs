@@ -0,0 +1,130 @@
+use std::{cmp, fmt};
+
+use super::backend::{self, Backend, ProbingBackend, Segment};
+use super::{imp, open_segment, Error, LinkedMem, Position, MAX_CONTEXT_LEN};
+
+/// A read-only view of the Mumble link, showing what Mumble will see.
+///
+/// Useful for tools and tests which want to check what a game, or another
+/// application, is writing to the link. Opening the reader looks for the link
+/// and fails the same way as `MumbleLink::new()`.
+pub struct LinkReader {
+    backend: Box<dyn Backend>,
+    segment: Box<dyn Segment>,
+}
+
+impl LinkReader {
+    /// Open the Mumble link for reading.
+    pub fn open() -> Result<LinkReader, Error> {
+        LinkReader::with_backend(Box::new(ProbingBackend::from_env()))
+    }
+
+    /// Open the link stored in `backend` for reading.
+    pub fn with_backend(backend: Box<dyn Backend>) -> Result<LinkReader, Error> {
+        Ok(LinkReader {
+            segment: open_segment(&*backend, false)?,
+            backend,
+        })
+    }
+
+    /// Read and decode the current contents of the link.
+    pub fn read(&self) -> Frame {
+        Frame::decode(&LinkedMem::read(&*self.segment))
+    }
+
+    /// Where the link data is read from, such as the name of the shared
+    /// memory segment.
+    pub fn location(&self) -> String {
+        self.backend.name()
+    }
+
+    /// Whether Mumble has been restarted since the reader was opened, in
+    /// which case it should be reopened to see the new segment.
+    pub fn is_stale(&self) -> bool {
+        backend::is_stale(&*self.backend, &*self.segment)
+    }
+}
+
+impl fmt::Debug for LinkReader {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("LinkReader")
+            .field("backend", &self.backend.name())
+            .finish()
+    }
+}
+
+/// A decoded snapshot of the Mumble link.
+#[derive(Clone, Debug)]
+pub struct Frame {
+    /// The version of the link structure. Zero if nothing is using the link.
+    pub version: u32,
+    /// Incremented by the writer on every update. Mumble stops using the
+    /// position data if it stops changing.
+    pub tick: u32,
+    /// The position of the player.
+    pub avatar: Position,
+    /// The position of the camera.
+    pub camera: Position,
+    /// The name of the application writing to the link.
+    pub name: String,
+    /// The identity of the player.
+    pub identity: String,
+    /// The context, which decides which players hear each other.
+    pub context: Vec<u8>,
+    /// The description of the application writing to the link.
+    pub description: String,
+}
+
+impl Frame {
+    pub(crate) fn decode(mem: &LinkedMem) -> Frame {
+        let context_len = cmp::min(mem.context_len as usize, MAX_CONTEXT_LEN);
+        Frame {
+            version: mem.ui_version,
+            tick: mem.ui_tick,
+            avatar: mem.avatar,
+            camera: mem.camera,
+            name: imp::read(&mem.name),
+            identity: imp::read(&mem.identity),
+            context: mem.context[..context_len].to_vec(),
+            description: imp::read(&mem.description),
+        }
+    }
+
+    /// Whether an application is using the link.
+    pub fn is_active(&self) -> bool {
+        self.version != 0
+    }
+}
+
+#[test]
+fn test_reader() {
+    use super::{MemoryBackend, LINK_SIZE};
+
+    let backend = MemoryBackend::new();
+    assert!(LinkReader::with_backend(Box::new(backend.clone())).is_err());
+    let mut writer = backend.create(LINK_SIZE).unwrap();
+
+    let reader = LinkReader::with_backend(Box::new(backend.clone())).unwrap();
+    assert!(!reader.read().is_active());
+
+    let mut mem = LinkedMem::new("Game", "A game.");
+    mem.set_context(b"server").unwrap();
+    mem.set_identity("Steve").unwrap();
+    mem.update(Position::default(), Position::default());
+    mem.publish(&mut *writer, true);
+
+    let frame = reader.read();
+    assert!(frame.is_active());
+    assert_eq!(2, frame.version);
+    assert_eq!(1, frame.tick);
+    assert_eq!([0., 1., 0.], frame.camera.top);
+    assert_eq!("Game", frame.name);
+    assert_eq!("Steve", frame.identity);
+    assert_eq!(b"server", &frame.context[..]);
+    assert_eq!("A game.", frame.description);
+
+    // Like Mumble restarting
+    assert!(!reader.is_stale());
+    backend.create(LINK_SIZE).unwrap();
+    assert!(reader.is_stale());
+}
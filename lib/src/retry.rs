@@ -0,0 +1,121 @@
+use std::time::Duration;
+
+use super::RETRY_INTERVAL;
+
+/// How a `SharedLink` which is not active tries to become active.
+///
+/// While the link is closed, it tries to open it every `interval`, waiting
+/// longer after each failure if there is a backoff, and gives up after
+/// `max_attempts` failures in a row. While another application is using the
+/// link, it checks every `interval` whether that application is still
+/// updating it, and takes over once it has stood still for the takeover grace
+/// period.
+///
+/// All of this is based on wall-clock time, checked on `update()`, so it
+/// doesn't depend on the frame rate. The default retries every second,
+/// forever, and takes over after a second.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RetryPolicy {
+    interval: Duration,
+    backoff: f64,
+    max_interval: Duration,
+    max_attempts: Option<u32>,
+    takeover_grace: Duration,
+}
+
+impl RetryPolicy {
+    pub fn new() -> RetryPolicy {
+        RetryPolicy {
+            interval: RETRY_INTERVAL,
+            backoff: 1.,
+            max_interval: RETRY_INTERVAL,
+            max_attempts: None,
+            takeover_grace: RETRY_INTERVAL,
+        }
+    }
+
+    /// How long to wait between checks, and before the first retry.
+    pub fn interval(mut self, interval: Duration) -> RetryPolicy {
+        self.interval = interval;
+        self.max_interval = self.max_interval.max(interval);
+        self
+    }
+
+    /// Multiply the wait by `factor` after every failed attempt to open the
+    /// link, up to `max_interval`.
+    ///
+    /// # Panics
+    ///
+    /// If `factor` is less than 1.
+    pub fn backoff(mut self, factor: f64, max_interval: Duration) -> RetryPolicy {
+        assert!(factor >= 1., "backoff factor {} is less than 1", factor);
+        self.backoff = factor;
+        self.max_interval = max_interval.max(self.interval);
+        self
+    }
+
+    /// Stop trying to open the link after `attempts` failed retries in a row.
+    /// The count starts over once the link opens, or when it is
+    /// `deactivate()`d.
+    pub fn max_attempts(mut self, attempts: u32) -> RetryPolicy {
+        self.max_attempts = Some(attempts);
+        self
+    }
+
+    /// How long another application's tick must stand still before the link
+    /// is taken over from it. Mumble itself stops listening to an application
+    /// after five seconds; see `LINK_TIMEOUT`.
+    pub fn takeover_grace(mut self, grace: Duration) -> RetryPolicy {
+        self.takeover_grace = grace;
+        self
+    }
+
+    /// How long to wait after `failures` failed retries in a row.
+    pub(crate) fn delay(&self, failures: u32) -> Duration {
+        let exponent = failures.min(i32::MAX as u32) as i32;
+        let secs = self.interval.as_secs_f64() * self.backoff.powi(exponent);
+        if secs < self.max_interval.as_secs_f64() {
+            Duration::from_secs_f64(secs)
+        } else {
+            self.max_interval
+        }
+    }
+
+    pub(crate) fn check_interval(&self) -> Duration {
+        self.interval
+    }
+
+    /// Whether to stop trying after `failures` failed retries in a row.
+    pub(crate) fn gives_up(&self, failures: u32) -> bool {
+        self.max_attempts.is_some_and(|max| failures >= max)
+    }
+
+    pub(crate) fn grace(&self) -> Duration {
+        self.takeover_grace
+    }
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy::new()
+    }
+}
+
+#[test]
+fn test_delay() {
+    let policy = RetryPolicy::new();
+    assert_eq!(RETRY_INTERVAL, policy.delay(0));
+    assert_eq!(RETRY_INTERVAL, policy.delay(10));
+    assert!(!policy.gives_up(1000));
+
+    let policy = RetryPolicy::new()
+        .interval(Duration::from_millis(100))
+        .backoff(2., Duration::from_secs(1))
+        .max_attempts(3);
+    assert_eq!(Duration::from_millis(100), policy.delay(0));
+    assert_eq!(Duration::from_millis(400), policy.delay(2));
+    assert_eq!(Duration::from_secs(1), policy.delay(4));
+    assert_eq!(Duration::from_secs(1), policy.delay(u32::MAX));
+    assert!(!policy.gives_up(2));
+    assert!(policy.gives_up(3));
+}
@@ -0,0 +1,137 @@
+use std::sync::{Arc, Mutex};
+
+use super::backend::lock;
+use super::{MumbleLink, Position, SharedLink, Status, Truncated};
+
+/// A `SharedLink` or `MumbleLink` which can be cloned into several threads.
+///
+/// Every clone uses the same link, so the render thread can `update()` it
+/// while the network thread sets the context and a UI thread shows the
+/// status. Each call waits for any other thread using the link.
+pub struct SyncLink<L = SharedLink> {
+    link: Arc<Mutex<L>>,
+}
+
+impl<L> SyncLink<L> {
+    pub fn new(link: L) -> SyncLink<L> {
+        SyncLink {
+            link: Arc::new(Mutex::new(link)),
+        }
+    }
+
+    /// Use the link directly, such as to subscribe to its status changes.
+    pub fn with_link<R, F: FnOnce(&mut L) -> R>(&self, f: F) -> R {
+        f(&mut lock(&self.link))
+    }
+}
+
+impl<L> Clone for SyncLink<L> {
+    fn clone(&self) -> Self {
+        SyncLink {
+            link: self.link.clone(),
+        }
+    }
+}
+
+impl<L> From<L> for SyncLink<L> {
+    fn from(link: L) -> SyncLink<L> {
+        SyncLink::new(link)
+    }
+}
+
+macro_rules! shared_methods {
+    ($link:ident) => {
+        impl SyncLink<$link> {
+            #[doc = concat!("Like `", stringify!($link), "::set_context()`.")]
+            pub fn set_context(&self, context: &[u8]) -> Result<(), Truncated> {
+                lock(&self.link).set_context(context)
+            }
+
+            #[doc = concat!("Like `", stringify!($link), "::set_identity()`.")]
+            pub fn set_identity(&self, identity: &str) -> Result<(), Truncated> {
+                lock(&self.link).set_identity(identity)
+            }
+
+            #[doc = concat!("Like `", stringify!($link), "::update()`.")]
+            pub fn update(&self, avatar: Position, camera: Position) {
+                lock(&self.link).update(avatar, camera)
+            }
+
+            #[doc = concat!("Like `", stringify!($link), "::location()`.")]
+            pub fn location(&self) -> String {
+                lock(&self.link).location()
+            }
+        }
+
+        impl std::fmt::Debug for SyncLink<$link> {
+            fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                f.debug_struct("SyncLink")
+                    .field("location", &self.location())
+                    .finish()
+            }
+        }
+    };
+}
+
+shared_methods!(MumbleLink);
+shared_methods!(SharedLink);
+
+impl SyncLink<SharedLink> {
+    /// Look at the status of the link, which stays as it is until `f`
+    /// returns. See `SharedLink::status()`.
+    pub fn status<R, F: FnOnce(Status<'_>) -> R>(&self, f: F) -> R {
+        f(lock(&self.link).status())
+    }
+
+    /// Like `SharedLink::deactivate()`, for every clone.
+    pub fn deactivate(&self) {
+        lock(&self.link).deactivate()
+    }
+}
+
+#[test]
+fn test_sync() {
+    use super::{Backend, LinkedMem, MemoryBackend, LINK_SIZE};
+    use std::thread;
+
+    let backend = MemoryBackend::new();
+    let segment = backend.create(LINK_SIZE).unwrap();
+    let link = SyncLink::new(SharedLink::with_backend(
+        Box::new(backend.clone()),
+        "Game",
+        "",
+    ));
+
+    let threads: Vec<_> = (0..4)
+        .map(|i| {
+            let link = link.clone();
+            thread::spawn(move || {
+                for _ in 0..100 {
+                    link.update(Position::default(), Position::default());
+                }
+                link.set_context(format!("thread {}", i).as_bytes())
+                    .unwrap();
+            })
+        })
+        .collect();
+    for thread in threads {
+        thread.join().unwrap();
+    }
+    link.set_identity("Steve").unwrap();
+    link.update(Position::default(), Position::default());
+
+    let mem = LinkedMem::read(&*segment);
+    assert_eq!(401, mem.ui_tick);
+    assert!(mem.context.starts_with(b"thread "));
+    assert!(link.status(|status| matches!(status, Status::Active)));
+    link.deactivate();
+
+    // The same goes for a `MumbleLink`
+    let link = SyncLink::new(MumbleLink::with_backend(Box::new(backend), "Game", "").unwrap());
+    let other = link.clone();
+    thread::spawn(move || other.set_context(b"other").unwrap())
+        .join()
+        .unwrap();
+    link.update(Position::default(), Position::default());
+    assert_eq!(b"other", &LinkedMem::read(&*segment).context[..5]);
+}
@@ -1,10 +1,18 @@
+use super::{Error, ErrorCode};
 use libc::{self, wchar_t};
 use std::ffi::CString;
-use std::ptr;
+use std::fs::File;
+use std::io;
+use std::os::unix::fs::MetadataExt;
+use std::os::unix::io::IntoRawFd;
+use std::{cmp, mem, ptr};
 
-pub fn copy(dest: &mut [wchar_t], src: &str) {
+/// Copy `src` into `dest` as a nul-terminated wide string, truncating it if
+/// needed. Returns the length of `src` in code units.
+pub fn copy(dest: &mut [wchar_t], src: &str) -> usize {
+    let len = src.chars().count();
     if dest.is_empty() {
-        return;
+        return len;
     }
     let mut index = 0;
     for ch in src.chars() {
@@ -15,6 +23,7 @@ pub fn copy(dest: &mut [wchar_t], src: &str) {
         index += 1;
     }
     dest[index] = 0;
+    len
 }
 
 pub fn read(src: &[wchar_t]) -> String {
@@ -28,29 +37,259 @@ pub fn read(src: &[wchar_t]) -> String {
 pub struct Map {
     fd: libc::c_int,
     pub ptr: *mut libc::c_void,
+    /// How many bytes are mapped, which is less than asked for if the
+    /// segment is smaller.
+    pub len: usize,
+    dev: libc::dev_t,
+    ino: libc::ino_t,
+    /// The segment to unlink when done, if we created it.
+    unlink: Option<CString>,
+}
+
+/// The name Mumble gives its segment: `/MumbleLink.<uid>`.
+pub fn default_name() -> String {
+    name_for_uid(unsafe { libc::getuid() })
+}
+
+pub fn name_for_uid(uid: u32) -> String {
+    format!("/MumbleLink.{}", uid)
+}
+
+/// The user a segment named like Mumble's belongs to, going by its name.
+pub fn uid_of(name: &str) -> Option<u32> {
+    name.strip_prefix("/MumbleLink.")?.parse().ok()
+}
+
+pub fn current_user() -> u32 {
+    unsafe { libc::geteuid() }
+}
+
+/// Where Mumble's segment may be, most likely first, with the user each is
+/// expected to belong to, or `None` for the current user. Without an explicit
+/// `uid`, this is the current user's, then the one of the user who ran `sudo`
+/// or `pkexec`, which belongs to them, then on Linux any other Mumble segment
+/// in `/dev/shm` which belongs to the current user, such as one named after a
+/// uid from another user namespace.
+pub fn candidates(uid: Option<u32>) -> Vec<(String, Option<u32>)> {
+    let mut names = Vec::new();
+    let owner = uid;
+    let uid = match uid {
+        Some(uid) => uid,
+        None => unsafe { libc::getuid() },
+    };
+    names.push((name_for_uid(uid), owner));
+
+    for var in &["SUDO_UID", "PKEXEC_UID"] {
+        if let Some(uid) = ::std::env::var(var).ok().and_then(|uid| uid.parse().ok()) {
+            names.push((name_for_uid(uid), Some(uid)));
+        }
+    }
+    if cfg!(target_os = "linux") {
+        if let Ok(entries) = ::std::fs::read_dir("/dev/shm") {
+            // Other users' segments would be refused anyway, and their error
+            // would hide that Mumble isn't running for this one
+            let mut found: Vec<_> = entries
+                .filter_map(|entry| {
+                    let entry = entry.ok()?;
+                    let name = entry.file_name().into_string().ok()?;
+                    let uid = name.strip_prefix("MumbleLink.")?.parse::<u32>();
+                    if uid.is_err() || entry.metadata().ok()?.uid() != current_user() {
+                        return None;
+                    }
+                    Some((format!("/{}", name), None))
+                })
+                .collect();
+            found.sort();
+            names.extend(found);
+        }
+    }
+
+    let mut seen = Vec::new();
+    names.retain(|(name, _)| {
+        let new = !seen.contains(name);
+        seen.push(name.clone());
+        new
+    });
+    names
+}
+
+/// A segment name no other test or process uses.
+#[cfg(test)]
+pub fn test_name(what: &str) -> String {
+    format!("/MumbleLink.test-{}.{}", what, unsafe { libc::getpid() })
+}
+
+/// The device and inode of the segment currently behind `name`. When Mumble
+/// restarts it unlinks the old segment and creates a new one, so a mapping
+/// whose `id()` differs is left writing into memory nobody reads.
+pub fn current_id(name: &str) -> Option<(u64, u64)> {
+    let path = CString::new(name).ok()?;
+    unsafe {
+        let fd = libc::shm_open(path.as_ptr(), libc::O_RDONLY, 0);
+        if fd < 0 {
+            return None;
+        }
+        let current = identify(fd);
+        libc::close(fd);
+        current.map(|stat| id(stat.st_dev, stat.st_ino))
+    }
 }
 
 impl Map {
-    pub fn new(size: usize) -> Result<Map, super::ErrorCode> {
+    /// Open an existing segment, mapping at most `size` bytes. Unless `owner`
+    /// is `None`, the segment must belong to that user and not be accessible
+    /// by anyone else.
+    pub fn open(name: &str, size: usize, writable: bool, owner: Option<u32>) -> Result<Map, Error> {
+        let flags = if writable {
+            libc::O_RDWR
+        } else {
+            libc::O_RDONLY
+        };
+        let path = shm_path(name)?;
+        let fd = unsafe { libc::shm_open(path.as_ptr(), flags, libc::S_IRUSR | libc::S_IWUSR) };
+        if fd < 0 {
+            return Err(Error::last_os_error(ErrorCode::ShmOpen, name));
+        }
+        Map::from_fd(fd, name, size, writable, owner)
+    }
+
+    /// Create the segment, and size and zero it the way Mumble does. An
+    /// existing segment, such as the one of a running Mumble, is left alone.
+    /// The segment is unlinked when the map is dropped.
+    pub fn create(name: &str, size: usize) -> Result<Map, Error> {
+        let path = shm_path(name)?;
         unsafe {
-            let path = CString::new(format!("/MumbleLink.{}", libc::getuid())).unwrap();
-            let fd = libc::shm_open(path.as_ptr(), libc::O_RDWR, libc::S_IRUSR | libc::S_IWUSR);
+            let fd = libc::shm_open(
+                path.as_ptr(),
+                libc::O_RDWR | libc::O_CREAT | libc::O_EXCL,
+                libc::S_IRUSR | libc::S_IWUSR,
+            );
             if fd < 0 {
-                return Err(super::ErrorCode::ShmOpen);
+                return Err(Error::last_os_error(ErrorCode::ShmOpen, name));
             }
-            let ptr = libc::mmap(
-                ptr::null_mut(),
-                size,
-                libc::PROT_READ | libc::PROT_WRITE,
-                libc::MAP_SHARED,
-                fd,
-                0,
-            );
+            if libc::ftruncate(fd, size as libc::off_t) != 0 {
+                let err = Error::last_os_error(ErrorCode::ShmOpen, name);
+                libc::close(fd);
+                libc::shm_unlink(path.as_ptr());
+                return Err(err);
+            }
+            let mut map = match Map::from_fd(fd, name, size, true, Some(current_user())) {
+                Ok(map) => map,
+                Err(err) => {
+                    libc::shm_unlink(path.as_ptr());
+                    return Err(err);
+                }
+            };
+            map.unlink = Some(path);
+            ptr::write_bytes(map.ptr as *mut u8, 0, map.len);
+            Ok(map)
+        }
+    }
+
+    /// Map a regular file, checked like `open()`.
+    pub fn file(
+        file: File,
+        name: &str,
+        size: usize,
+        writable: bool,
+        owner: Option<u32>,
+    ) -> Result<Map, Error> {
+        Map::from_fd(file.into_raw_fd(), name, size, writable, owner)
+    }
+
+    /// Map `fd`, taking ownership of it.
+    ///
+    /// Only the part of the segment which exists is mapped, since touching
+    /// pages past its end raises `SIGBUS`.
+    fn from_fd(
+        fd: libc::c_int,
+        name: &str,
+        size: usize,
+        writable: bool,
+        owner: Option<u32>,
+    ) -> Result<Map, Error> {
+        let prot = if writable {
+            libc::PROT_READ | libc::PROT_WRITE
+        } else {
+            libc::PROT_READ
+        };
+        unsafe {
+            let stat = match identify(fd) {
+                Some(stat) => stat,
+                None => {
+                    let err = Error::last_os_error(ErrorCode::ShmOpen, name);
+                    libc::close(fd);
+                    return Err(err);
+                }
+            };
+            if let Err(err) = check(&stat, name, owner) {
+                libc::close(fd);
+                return Err(err);
+            }
+            let len = cmp::min(size as u64, stat.st_size as u64) as usize;
+            if len == 0 {
+                libc::close(fd);
+                let err = io::Error::new(io::ErrorKind::InvalidData, "the segment is empty");
+                return Err(Error::too_small(name, err));
+            }
+            let ptr = libc::mmap(ptr::null_mut(), len, prot, libc::MAP_SHARED, fd, 0);
             if ptr as isize == -1 {
+                let err = Error::last_os_error(ErrorCode::MMap, name);
                 libc::close(fd);
-                return Err(super::ErrorCode::MMap);
+                return Err(err);
             }
-            Ok(Map { fd, ptr })
+            Ok(Map {
+                fd,
+                ptr,
+                len,
+                dev: stat.st_dev,
+                ino: stat.st_ino,
+                unlink: None,
+            })
+        }
+    }
+
+    /// The device and inode of the mapped segment.
+    pub fn id(&self) -> Option<(u64, u64)> {
+        Some(id(self.dev, self.ino))
+    }
+}
+
+/// Refuse a segment other users could read our player's position and
+/// identity from.
+fn check(stat: &libc::stat, name: &str, owner: Option<u32>) -> Result<(), Error> {
+    let owner = match owner {
+        Some(owner) => owner,
+        None => return Ok(()),
+    };
+    let message = if stat.st_uid != owner {
+        format!("owned by uid {} instead of {}", stat.st_uid, owner)
+    } else if stat.st_mode & 0o077 != 0 {
+        format!("mode {:o} gives other users access", stat.st_mode & 0o777)
+    } else {
+        return Ok(());
+    };
+    let err = io::Error::new(io::ErrorKind::PermissionDenied, message);
+    Err(Error::insecure(name, err))
+}
+
+// `dev_t` and `ino_t` are narrower than 64 bits on some targets
+#[allow(clippy::unnecessary_cast)]
+fn id(dev: libc::dev_t, ino: libc::ino_t) -> (u64, u64) {
+    (dev as u64, ino as u64)
+}
+
+fn shm_path(name: &str) -> Result<CString, Error> {
+    CString::new(name).map_err(|e| Error::new(ErrorCode::ShmOpen, name, e.into()))
+}
+
+fn identify(fd: libc::c_int) -> Option<libc::stat> {
+    unsafe {
+        let mut stat: libc::stat = mem::zeroed();
+        if libc::fstat(fd, &mut stat) == 0 {
+            Some(stat)
+        } else {
+            None
         }
     }
 }
@@ -58,7 +297,145 @@ impl Map {
 impl Drop for Map {
     fn drop(&mut self) {
         unsafe {
+            libc::munmap(self.ptr, self.len);
             libc::close(self.fd);
+            if let Some(ref path) = self.unlink {
+                libc::shm_unlink(path.as_ptr());
+            }
         }
     }
 }
+
+#[cfg(test)]
+fn create_segment(path: &CString, size: usize) {
+    unsafe {
+        let fd = libc::shm_open(
+            path.as_ptr(),
+            libc::O_RDWR | libc::O_CREAT,
+            libc::S_IRUSR | libc::S_IWUSR,
+        );
+        assert!(fd >= 0);
+        assert_eq!(0, libc::ftruncate(fd, size as libc::off_t));
+        libc::close(fd);
+    }
+}
+
+#[test]
+fn test_stale() {
+    let name = test_name("stale");
+    let path = CString::new(name.clone()).unwrap();
+    create_segment(&path, 64);
+
+    let map = Map::open(&name, 64, true, Some(current_user())).unwrap();
+    assert_eq!(map.id(), current_id(&name));
+
+    // Mumble closing unlinks the segment...
+    unsafe { libc::shm_unlink(path.as_ptr()) };
+    assert_eq!(None, current_id(&name));
+
+    // ...and reopening creates a fresh one under the same name.
+    create_segment(&path, 64);
+    assert!(current_id(&name).is_some());
+    assert!(map.id() != current_id(&name));
+    let fresh = Map::open(&name, 64, true, Some(current_user())).unwrap();
+    assert_eq!(fresh.id(), current_id(&name));
+
+    unsafe { libc::shm_unlink(path.as_ptr()) };
+}
+
+#[test]
+fn test_check() {
+    use super::ErrorKind;
+
+    let name = test_name("check");
+    let path = CString::new(name.clone()).unwrap();
+    let me = current_user();
+    create_segment(&path, 64);
+
+    assert!(Map::open(&name, 64, true, Some(me)).is_ok());
+    let err = Map::open(&name, 64, true, Some(me + 1)).err().unwrap();
+    assert_eq!(ErrorKind::Insecure, err.kind());
+    assert_eq!(ErrorCode::ShmOpen, err.code());
+
+    unsafe {
+        let fd = libc::shm_open(path.as_ptr(), libc::O_RDWR, 0);
+        libc::fchmod(fd, 0o644);
+        libc::close(fd);
+    }
+    let err = Map::open(&name, 64, true, Some(me)).err().unwrap();
+    assert_eq!(ErrorKind::Insecure, err.kind());
+    // Opting out
+    assert!(Map::open(&name, 64, true, None).is_ok());
+
+    unsafe { libc::shm_unlink(path.as_ptr()) };
+    assert_eq!(Some(42), uid_of("/MumbleLink.42"));
+    assert_eq!(None, uid_of(&name));
+}
+
+#[test]
+fn test_create() {
+    let name = test_name("create");
+    let map = Map::create(&name, 64).unwrap();
+    assert!(current_id(&name).is_some());
+    unsafe { *(map.ptr as *mut u8) = 1 };
+
+    // Another host, such as a real Mumble, is already there
+    let err = Map::create(&name, 64).err().unwrap();
+    assert_eq!(io::ErrorKind::AlreadyExists, err.os_error().kind());
+    assert_eq!(map.id(), current_id(&name));
+    let other = Map::open(&name, 64, false, Some(current_user())).unwrap();
+    assert_eq!(1, unsafe { *(other.ptr as *const u8) });
+
+    // Removed with the host which created it
+    drop(map);
+    assert_eq!(None, current_id(&name));
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn test_candidates() {
+    // Named like Mumble's, after a uid nobody has
+    let name = format!(
+        "/MumbleLink.{}",
+        4_000_000_000 + unsafe { libc::getpid() } as u32
+    );
+    let path = CString::new(name.clone()).unwrap();
+    create_segment(&path, 64);
+    assert!(candidates(None).contains(&(name.clone(), None)));
+
+    // Another user's is left out, which only root can try
+    if current_user() == 0 {
+        unsafe {
+            let fd = libc::shm_open(path.as_ptr(), libc::O_RDWR, 0);
+            assert_eq!(0, libc::fchown(fd, 65534, 65534));
+            libc::close(fd);
+        }
+        assert!(!candidates(None).iter().any(|c| c.0 == name));
+    }
+
+    unsafe { libc::shm_unlink(path.as_ptr()) };
+}
+
+#[test]
+fn test_size() {
+    use super::ErrorKind;
+
+    let name = test_name("size");
+    let path = CString::new(name.clone()).unwrap();
+
+    // Not sized yet, like right after Mumble creates it
+    create_segment(&path, 0);
+    let err = Map::open(&name, 128, true, None).err().unwrap();
+    assert_eq!(ErrorKind::TooSmall, err.kind());
+
+    // Only the part which exists is mapped
+    create_segment(&path, 64);
+    let map = Map::open(&name, 128, true, None).unwrap();
+    assert_eq!(64, map.len);
+    unsafe { ptr::write_bytes(map.ptr as *mut u8, 1, map.len) };
+    drop(map);
+    create_segment(&path, 256);
+    assert_eq!(128, Map::open(&name, 128, false, None).unwrap().len);
+
+    unsafe { libc::shm_unlink(path.as_ptr()) };
+}
@@ -0,0 +1,186 @@
+use std::mem;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard, Weak};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use super::backend::lock;
+use super::{LinkedMem, Position, Segment, SegmentId};
+
+/// Zeroes the avatar position of a link which hasn't been updated for a
+/// while, such as when the game froze or is in a menu, so Mumble stops
+/// positioning the player where they were last. The next update writes the
+/// avatar again.
+///
+/// The watchdog runs on its own thread, and sees the link's segments through
+/// the `Watched` wrappers returned by `watch()`.
+pub(crate) struct Watchdog {
+    shared: Arc<Shared>,
+    thread: Option<JoinHandle<()>>,
+}
+
+struct Shared {
+    state: Mutex<State>,
+    wake: Condvar,
+}
+
+struct State {
+    idle: Duration,
+    /// The segment the link writes to now.
+    segment: Weak<Mutex<Box<dyn Segment>>>,
+    updated_at: Instant,
+    /// The tick the link published last, until the avatar is zeroed.
+    published: Option<u32>,
+    stop: bool,
+}
+
+impl Watchdog {
+    /// Start a watchdog which zeroes the avatar after `idle` without writes.
+    pub(crate) fn spawn(idle: Duration) -> Watchdog {
+        let shared = Arc::new(Shared {
+            state: Mutex::new(State {
+                idle,
+                segment: Weak::new(),
+                updated_at: Instant::now(),
+                published: None,
+                stop: false,
+            }),
+            wake: Condvar::new(),
+        });
+        let thread = {
+            let shared = shared.clone();
+            thread::Builder::new()
+                .name("mumble-link-watchdog".to_owned())
+                .spawn(move || run(&shared))
+                .expect("failed to spawn the watchdog thread")
+        };
+        Watchdog {
+            shared,
+            thread: Some(thread),
+        }
+    }
+
+    /// Zero the avatar after `idle` without writes from now on.
+    pub(crate) fn set_idle(&self, idle: Duration) {
+        lock(&self.shared.state).idle = idle;
+        self.shared.wake.notify_all();
+    }
+
+    /// Watch `segment`, which replaces any segment watched before.
+    pub(crate) fn watch(&self, segment: Box<dyn Segment>) -> Box<dyn Segment> {
+        let segment = Arc::new(Mutex::new(segment));
+        let mut state = lock(&self.shared.state);
+        state.segment = Arc::downgrade(&segment);
+        state.updated_at = Instant::now();
+        state.published = None;
+        Box::new(Watched {
+            shared: self.shared.clone(),
+            segment,
+        })
+    }
+
+    /// Like `watch()`, for a segment which is already in use.
+    pub(crate) fn watch_in_place(&self, segment: &mut Box<dyn Segment>) {
+        let unwatched = mem::replace(segment, Box::new(Detached));
+        *segment = self.watch(unwatched);
+    }
+}
+
+fn run(shared: &Shared) {
+    let mut state = lock(&shared.state);
+    while !state.stop {
+        let elapsed = state.updated_at.elapsed();
+        let published = match state.published {
+            Some(tick) if elapsed >= state.idle => tick,
+            _ => {
+                let wait = match state.published {
+                    Some(_) => state.idle - elapsed,
+                    None => state.idle,
+                };
+                state = shared
+                    .wake
+                    .wait_timeout(state, wait.max(Duration::from_millis(1)))
+                    .unwrap_or_else(|e| e.into_inner())
+                    .0;
+                continue;
+            }
+        };
+        state.published = None;
+        let segment = match state.segment.upgrade() {
+            Some(segment) => segment,
+            None => continue,
+        };
+        let mut segment = lock(&segment);
+        // Leave the avatar alone if another application wrote since
+        let (_, tick) = LinkedMem::read_header(&**segment);
+        if tick == published {
+            debug!("No update for {:?}, zeroing the avatar", elapsed);
+            let zeros = [0; mem::size_of::<Position>()];
+            segment.write(mem::offset_of!(LinkedMem, avatar), &zeros);
+        }
+    }
+}
+
+impl Drop for Watchdog {
+    fn drop(&mut self) {
+        lock(&self.shared.state).stop = true;
+        self.shared.wake.notify_all();
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// A segment shared with a `Watchdog`. Every write counts as an update, and
+/// every published tick arms the watchdog.
+struct Watched {
+    shared: Arc<Shared>,
+    segment: Arc<Mutex<Box<dyn Segment>>>,
+}
+
+impl Watched {
+    /// Note the update before writing, so the watchdog doesn't zero the
+    /// avatar of a frame as it is being written.
+    fn updating(&self) -> MutexGuard<'_, State> {
+        let mut state = lock(&self.shared.state);
+        state.updated_at = Instant::now();
+        state
+    }
+}
+
+impl Segment for Watched {
+    fn size(&self) -> usize {
+        lock(&self.segment).size()
+    }
+
+    fn read(&self, buf: &mut [u8]) {
+        lock(&self.segment).read(buf)
+    }
+
+    fn write(&mut self, offset: usize, data: &[u8]) {
+        let _state = self.updating();
+        lock(&self.segment).write(offset, data)
+    }
+
+    fn publish(&mut self, offset: usize, value: u32) {
+        let mut state = self.updating();
+        lock(&self.segment).publish(offset, value);
+        state.published = Some(value);
+    }
+
+    fn id(&self) -> Option<SegmentId> {
+        lock(&self.segment).id()
+    }
+}
+
+/// Stands in for a segment while it is moved into a `Watched`.
+struct Detached;
+
+impl Segment for Detached {
+    fn size(&self) -> usize {
+        0
+    }
+
+    fn read(&self, _buf: &mut [u8]) {}
+
+    fn write(&mut self, _offset: usize, _data: &[u8]) {}
+}
@@ -1,10 +1,18 @@
 extern crate kernel32;
 extern crate winapi;
 
+use super::{Error, ErrorCode};
 use libc::{c_void, wchar_t};
+use std::fs::File;
+use std::io;
+use std::os::windows::io::AsRawHandle;
+use std::{cmp, mem, ptr};
 
-pub fn copy(dest: &mut [wchar_t], src: &str) {
-    if dest.is_empty() { return }
+/// Copy `src` into `dest` as a nul-terminated wide string, truncating it if
+/// needed. Returns the length of `src` in code units.
+pub fn copy(dest: &mut [wchar_t], src: &str) -> usize {
+    let len = src.encode_utf16().count();
+    if dest.is_empty() { return len }
     let mut index = 0;
     for ch in src.encode_utf16() {
         if index == dest.len() - 1 { break }
@@ -12,6 +20,7 @@ pub fn copy(dest: &mut [wchar_t], src: &str) {
         index += 1;
     }
     dest[index] = 0;
+    len
 }
 
 pub fn read(src: &[wchar_t]) -> String {
@@ -22,41 +31,150 @@ pub fn read(src: &[wchar_t]) -> String {
 pub struct Map {
     handle: winapi::HANDLE,
     pub ptr: *mut c_void,
+    /// How many bytes are mapped, which is less than asked for if the
+    /// mapping is smaller.
+    pub len: usize,
+}
+
+/// The name Mumble gives its file mapping.
+pub fn default_name() -> String {
+    "MumbleLink".to_owned()
+}
+
+/// File mappings are per session rather than per user, so there is only one.
+pub fn name_for_uid(_uid: u32) -> String {
+    default_name()
+}
+
+/// Ownership isn't checked on Windows, where the mapping is per session.
+pub fn uid_of(_name: &str) -> Option<u32> {
+    None
+}
+
+pub fn current_user() -> u32 {
+    0
+}
+
+/// See `name_for_uid`.
+pub fn candidates(_uid: Option<u32>) -> Vec<(String, Option<u32>)> {
+    vec![(default_name(), None)]
+}
+
+/// A mapping name no other test or process uses.
+#[cfg(test)]
+pub fn test_name(what: &str) -> String {
+    format!("MumbleLink.test-{}.{}", what, unsafe { kernel32::GetCurrentProcessId() })
 }
 
 impl Map {
-    pub fn new(size: usize) -> Result<Map, super::ErrorCode> {
+    /// Open an existing file mapping, mapping at most `size` bytes.
+    pub fn open(name: &str, size: usize, writable: bool, _owner: Option<u32>) -> Result<Map, Error> {
+        let access = if writable { winapi::FILE_MAP_ALL_ACCESS } else { winapi::FILE_MAP_READ };
+        let wide: Vec<wchar_t> = name.encode_utf16().chain(Some(0)).collect();
+        unsafe {
+            let handle = kernel32::OpenFileMappingW(access, winapi::FALSE, wide.as_ptr());
+            if handle.is_null() {
+                return Err(Error::last_os_error(ErrorCode::OpenFileMappingW, name));
+            }
+            Map::view(handle, name, size, access)
+        }
+    }
+
+    /// Create the file mapping, and zero it the way Mumble does. An existing
+    /// mapping, such as the one of a running Mumble, is left alone. The
+    /// mapping goes away once every handle to it is closed.
+    pub fn create(name: &str, size: usize) -> Result<Map, Error> {
+        let wide: Vec<wchar_t> = name.encode_utf16().chain(Some(0)).collect();
         unsafe {
-            let handle = kernel32::OpenFileMappingW(
-                winapi::FILE_MAP_ALL_ACCESS,
-                winapi::FALSE,
-                wide!(M u m b l e L i n k).as_ptr(),
+            let handle = kernel32::CreateFileMappingW(
+                winapi::INVALID_HANDLE_VALUE,
+                ptr::null_mut(),
+                winapi::PAGE_READWRITE,
+                0,
+                size as winapi::DWORD,
+                wide.as_ptr(),
             );
             if handle.is_null() {
-                return Err(super::ErrorCode::OpenFileMappingW);
+                return Err(Error::last_os_error(ErrorCode::OpenFileMappingW, name));
+            }
+            if kernel32::GetLastError() == winapi::ERROR_ALREADY_EXISTS {
+                kernel32::CloseHandle(handle);
+                let err = io::Error::from(io::ErrorKind::AlreadyExists);
+                return Err(Error::new(ErrorCode::OpenFileMappingW, name, err));
             }
-            let ptr = kernel32::MapViewOfFile(
-                handle,
-                winapi::FILE_MAP_ALL_ACCESS,
+            let map = Map::view(handle, name, size, winapi::FILE_MAP_ALL_ACCESS)?;
+            ptr::write_bytes(map.ptr as *mut u8, 0, map.len);
+            Ok(map)
+        }
+    }
+
+    /// Map all of `handle` and use up to `size` bytes of it. Asking for more
+    /// than the mapping holds would fail, so the view's real size is looked
+    /// up instead.
+    unsafe fn view(handle: winapi::HANDLE, name: &str, size: usize, access: winapi::DWORD) -> Result<Map, Error> {
+        let ptr = kernel32::MapViewOfFile(handle, access, 0, 0, 0);
+        if ptr.is_null() {
+            let err = Error::last_os_error(ErrorCode::MapViewOfFile, name);
+            kernel32::CloseHandle(handle);
+            return Err(err);
+        }
+        let mut info: winapi::MEMORY_BASIC_INFORMATION = mem::zeroed();
+        let len = mem::size_of::<winapi::MEMORY_BASIC_INFORMATION>() as winapi::SIZE_T;
+        if kernel32::VirtualQuery(ptr, &mut info, len) == 0 {
+            let err = Error::last_os_error(ErrorCode::MapViewOfFile, name);
+            kernel32::UnmapViewOfFile(ptr);
+            kernel32::CloseHandle(handle);
+            return Err(err);
+        }
+        Ok(Map {
+            handle,
+            ptr: ptr as *mut c_void,
+            len: cmp::min(size, info.RegionSize as usize),
+        })
+    }
+
+    /// Map a regular file, mapping at most `size` bytes. An empty file can't
+    /// be mapped.
+    pub fn file(file: File, name: &str, size: usize, writable: bool, _owner: Option<u32>) -> Result<Map, Error> {
+        let (protect, access) = if writable {
+            (winapi::PAGE_READWRITE, winapi::FILE_MAP_ALL_ACCESS)
+        } else {
+            (winapi::PAGE_READONLY, winapi::FILE_MAP_READ)
+        };
+        unsafe {
+            // The mapping keeps the file open, so `file` may be closed
+            let handle = kernel32::CreateFileMappingW(
+                file.as_raw_handle() as winapi::HANDLE,
+                ptr::null_mut(),
+                protect,
                 0,
                 0,
-                size as u64,
+                ptr::null(),
             );
-            if ptr.is_null() {
-                kernel32::CloseHandle(handle);
-                return Err(super::ErrorCode::MapViewOfFile);
+            if handle.is_null() {
+                return Err(Error::last_os_error(ErrorCode::OpenFileMappingW, name));
             }
-            Ok(Map {
-                handle,
-                ptr: ptr as *mut c_void,
-            })
+            Map::view(handle, name, size, access)
         }
     }
+
+    /// Named file mappings live as long as any handle is open, so a restarted
+    /// Mumble reattaches to the mapping we already hold, and there is nothing
+    /// to tell apart.
+    pub fn id(&self) -> Option<(u64, u64)> {
+        None
+    }
+}
+
+/// See `Map::id`.
+pub fn current_id(_name: &str) -> Option<(u64, u64)> {
+    None
 }
 
 impl Drop for Map {
     fn drop(&mut self) {
         unsafe {
+            kernel32::UnmapViewOfFile(self.ptr as winapi::LPCVOID);
             kernel32::CloseHandle(self.handle);
         }
     }